@@ -1,4 +1,4 @@
-use nalgebra::Vector3;
+use nalgebra::{Matrix4, Point3, Vector3};
 
 use crate::mesh::Mesh;
 
@@ -32,7 +32,42 @@ impl Bbox {
         Bbox { min, max }
     }
 
-    pub fn from_pcl(pcl: &[Vector3<f32>]) -> Self {
+    pub fn from_meshes(meshes: &[Mesh]) -> Self {
+        meshes
+            .iter()
+            .map(|mesh| *mesh.bbox())
+            .reduce(|a, b| a.union(&b))
+            .unwrap_or(Bbox::new(Vector3::zeros(), Vector3::zeros()))
+    }
+
+    pub fn union(&self, other: &Bbox) -> Self {
+        Self {
+            min: self.min.inf(&other.min),
+            max: self.max.sup(&other.max),
+        }
+    }
+
+    pub fn size(&self) -> Vector3<f32> {
+        self.max - self.min
+    }
+
+    /// Bounding box of the transformed corners
+    pub fn transform(&self, matrix: &Matrix4<f32>) -> Self {
+        let corners = (0..8)
+            .map(|i| {
+                let corner = Vector3::from_fn(|axis, _| match (i >> axis) & 1 {
+                    0 => self.min[axis],
+                    _ => self.max[axis],
+                });
+
+                matrix.transform_point(&Point3::from(corner)).coords
+            })
+            .collect::<Vec<_>>();
+
+        Self::from_pcl(&corners)
+    }
+
+    pub fn from_pcl<'a>(pcl: impl IntoIterator<Item = &'a Vector3<f32>>) -> Self {
         let mut min = Vector3::new(f32::MAX, f32::MAX, f32::MAX);
         let mut max = Vector3::new(f32::MIN, f32::MIN, f32::MIN);
 
@@ -0,0 +1,177 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, Read},
+    path::Path,
+};
+
+use nalgebra::Vector3;
+
+use crate::pointcloud::{LocalOrigin, PointCloud};
+
+/// Filters of reconstructed points
+#[derive(Clone, Copy, Debug)]
+pub struct ColmapOptions {
+    /// Points seen by fewer images are dropped
+    pub min_track_length: usize,
+    /// Points with a larger mean reprojection error in pixels are dropped
+    pub max_error: Option<f64>,
+}
+
+impl Default for ColmapOptions {
+    fn default() -> Self {
+        Self {
+            min_track_length: 1,
+            max_error: None,
+        }
+    }
+}
+
+impl ColmapOptions {
+    fn keeps(&self, track_length: usize, error: f64) -> bool {
+        track_length >= self.min_track_length && self.max_error.is_none_or(|max| error <= max)
+    }
+}
+
+/// Whether a file is a COLMAP sparse reconstruction, `points3D.bin` or a
+/// binary file next to the `images.bin` of a sparse model, or a text export
+/// starting with its header comment
+pub fn is_colmap_points<P: AsRef<Path>>(path: P) -> bool {
+    let path = path.as_ref();
+
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("bin") => {
+            path.file_name().is_some_and(|name| name == "points3D.bin")
+                || path.with_file_name("images.bin").is_file()
+        }
+        Some("txt") => {
+            let mut line = String::new();
+
+            BufReader::new(File::open(path).unwrap())
+                .read_line(&mut line)
+                .is_ok_and(|_| line.starts_with("# 3D point list"))
+        }
+        _ => false,
+    }
+}
+
+/// Loads the points of a COLMAP sparse reconstruction, `points3D.txt` or
+/// `points3D.bin`. Returns the point cloud and the number of points dropped
+/// by the filters
+pub fn load_colmap_points<P: AsRef<Path>>(
+    path: P,
+    options: &ColmapOptions,
+    origin: &LocalOrigin,
+) -> (PointCloud, usize) {
+    let path = path.as_ref();
+
+    let mut reader = BufReader::new(File::open(path).unwrap());
+
+    // Position, colour, reprojection error and track length of every point
+    let mut points: Vec<(Vector3<f64>, [u8; 4], f64, usize)> = Vec::new();
+
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("bin") => {
+            let count = read_u64(&mut reader);
+
+            for _ in 0..count {
+                let _id = read_u64(&mut reader);
+                let position = Vector3::new(
+                    read_f64(&mut reader),
+                    read_f64(&mut reader),
+                    read_f64(&mut reader),
+                );
+
+                let mut color = [255u8; 4];
+                reader.read_exact(&mut color[..3]).unwrap();
+
+                let error = read_f64(&mut reader);
+                let track_length = read_u64(&mut reader) as usize;
+
+                // Image id and 2D point index of every observation
+                let mut track = vec![0u8; track_length * 8];
+                reader.read_exact(&mut track).unwrap();
+
+                points.push((position, color, error, track_length));
+            }
+        }
+        _ => {
+            for line in reader.lines() {
+                let line = line.unwrap();
+                let line = line.trim();
+
+                if line.is_empty() || line.starts_with('#') {
+                    continue;
+                }
+
+                // POINT3D_ID, X, Y, Z, R, G, B, ERROR, TRACK[] as
+                // (IMAGE_ID, POINT2D_IDX)
+                let values = line.split_whitespace().collect::<Vec<&str>>();
+
+                assert!(values.len() >= 8, "Invalid COLMAP point '{}'", line);
+
+                let number = |i: usize| {
+                    values[i]
+                        .parse::<f64>()
+                        .unwrap_or_else(|_| panic!("Invalid COLMAP point '{}'", line))
+                };
+
+                points.push((
+                    Vector3::new(number(1), number(2), number(3)),
+                    [number(4) as u8, number(5) as u8, number(6) as u8, 255],
+                    number(7),
+                    (values.len() - 8) / 2,
+                ));
+            }
+        }
+    }
+
+    let count = points.len();
+
+    let points = points
+        .into_iter()
+        .filter(|(_, _, error, track_length)| options.keeps(*track_length, *error))
+        .map(|(position, color, _, _)| (position, color))
+        .collect::<Vec<_>>();
+
+    let filtered = count - points.len();
+
+    (PointCloud::from_global(points, origin), filtered)
+}
+
+/// Number of images seeing every point of a COLMAP dense `fused.ply`, read
+/// from the `fused.ply.vis` file next to it
+pub fn fused_track_lengths<P: AsRef<Path>>(path: P) -> Option<Vec<usize>> {
+    let mut vis = path.as_ref().as_os_str().to_owned();
+    vis.push(".vis");
+
+    let mut reader = BufReader::new(File::open(vis).ok()?);
+
+    let count = read_u64(&mut reader);
+    let mut track_lengths = Vec::with_capacity(count as usize);
+
+    for _ in 0..count {
+        let mut length = [0u8; 4];
+        reader.read_exact(&mut length).unwrap();
+        let length = u32::from_le_bytes(length) as usize;
+
+        // Indices of the images
+        let mut images = vec![0u8; length * 4];
+        reader.read_exact(&mut images).unwrap();
+
+        track_lengths.push(length);
+    }
+
+    Some(track_lengths)
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> u64 {
+    let mut bytes = [0u8; 8];
+    reader.read_exact(&mut bytes).unwrap();
+    u64::from_le_bytes(bytes)
+}
+
+fn read_f64<R: Read>(reader: &mut R) -> f64 {
+    let mut bytes = [0u8; 8];
+    reader.read_exact(&mut bytes).unwrap();
+    f64::from_le_bytes(bytes)
+}
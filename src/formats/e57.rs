@@ -0,0 +1,60 @@
+use std::path::Path;
+
+use e57::{CartesianCoordinate, E57Reader};
+use nalgebra::Vector3;
+
+use crate::pointcloud::{LocalOrigin, PointCloud};
+
+/// Loads every scan of an E57 file, with its pose applied. Spherical records
+/// are converted to Cartesian ones and points without colour are coloured by
+/// their intensity. The position of every scan is kept as the sensor origin
+/// of its points
+pub fn load_e57<P: AsRef<Path>>(path: P, origin: &LocalOrigin) -> PointCloud {
+    let mut reader = E57Reader::from_file(path.as_ref()).expect("Invalid e57 file");
+
+    let mut points = Vec::new();
+    let mut sensors = Vec::new();
+
+    for scan in reader.pointclouds() {
+        let sensor = match &scan.transform {
+            Some(transform) => Vector3::new(
+                transform.translation.x,
+                transform.translation.y,
+                transform.translation.z,
+            ),
+            None => Vector3::zeros(),
+        };
+
+        let iter = reader
+            .pointcloud_simple(&scan)
+            .expect("Failed to read e57 scan");
+
+        for point in iter {
+            let point = point.expect("Failed to read e57 point");
+
+            let position = match point.cartesian {
+                CartesianCoordinate::Valid { x, y, z } => Vector3::new(x, y, z),
+                // Directions without a range don't locate a point
+                CartesianCoordinate::Direction { .. } | CartesianCoordinate::Invalid => continue,
+            };
+
+            let color = match point.color {
+                Some(color) => [
+                    (color.red * 255.0) as u8,
+                    (color.green * 255.0) as u8,
+                    (color.blue * 255.0) as u8,
+                    255,
+                ],
+                None => [255u8; 4],
+            };
+
+            points.push((position, color));
+            sensors.push(sensor);
+        }
+    }
+
+    let mut pointcloud = PointCloud::from_global(points, origin);
+    pointcloud.set_sensors(sensors);
+
+    pointcloud
+}
@@ -0,0 +1,243 @@
+use gltf::{Document, Node, Scene};
+use serde_json::Value;
+
+/// Scenes of a glTF file to voxelize
+#[derive(Clone, Debug, Default)]
+pub enum SceneSelection {
+    /// Every scene of the file
+    #[default]
+    All,
+    Index(usize),
+    Name(String),
+}
+
+impl SceneSelection {
+    /// Parses a scene index, or a scene name when it isn't a number
+    pub fn parse(value: &str) -> Self {
+        match value.parse::<usize>() {
+            Ok(index) => Self::Index(index),
+            Err(_) => Self::Name(value.to_string()),
+        }
+    }
+
+    pub fn scenes<'a>(&self, document: &'a Document) -> Vec<Scene<'a>> {
+        match self {
+            Self::All => document.scenes().collect(),
+            Self::Index(index) => vec![document
+                .scenes()
+                .nth(*index)
+                .unwrap_or_else(|| panic!("Scene {} not found", index))],
+            Self::Name(name) => vec![document
+                .scenes()
+                .find(|scene| scene.name() == Some(name.as_str()))
+                .unwrap_or_else(|| panic!("Scene '{}' not found", name))],
+        }
+    }
+}
+
+/// Name patterns of helper geometry that isn't meant to be rendered
+const HELPER_PATTERNS: [&str; 10] = [
+    "*collider*",
+    "*collision*",
+    "ucx_*",
+    "ubx_*",
+    "ucp_*",
+    "usp_*",
+    "*_lod[1-9]*",
+    "*proxy*",
+    "*helper*",
+    "*navmesh*",
+];
+
+/// Node filters, a node filtered out also removes its children
+#[derive(Clone, Debug, Default)]
+pub struct NodeFilter {
+    /// Name globs of the nodes to keep, everything is kept when empty
+    pub include: Vec<String>,
+    /// Name globs of the nodes to drop
+    pub exclude: Vec<String>,
+    /// Extras of the nodes to keep, as `key` or `key=value`
+    pub include_extras: Vec<String>,
+    /// Extras of the nodes to drop, as `key` or `key=value`
+    pub exclude_extras: Vec<String>,
+    /// Drops colliders, LODs above 0, proxies and hidden nodes
+    pub skip_helpers: bool,
+}
+
+impl NodeFilter {
+    /// Whether every node is kept until a node matches an include filter
+    pub fn includes_all(&self) -> bool {
+        self.include.is_empty() && self.include_extras.is_empty()
+    }
+
+    pub fn includes(&self, node: &Node<'_>) -> bool {
+        let name = node.name().unwrap_or_default();
+
+        self.include.iter().any(|pattern| glob(pattern, name))
+            || self
+                .include_extras
+                .iter()
+                .any(|filter| extras_match(node, filter))
+    }
+
+    pub fn excludes(&self, node: &Node<'_>) -> bool {
+        let name = node.name().unwrap_or_default();
+
+        if self.exclude.iter().any(|pattern| glob(pattern, name))
+            || self
+                .exclude_extras
+                .iter()
+                .any(|filter| extras_match(node, filter))
+        {
+            return true;
+        }
+
+        self.skip_helpers && is_helper(node)
+    }
+}
+
+fn is_helper(node: &Node<'_>) -> bool {
+    let names = [
+        node.name().unwrap_or_default().to_lowercase(),
+        node.mesh()
+            .and_then(|mesh| mesh.name().map(str::to_lowercase))
+            .unwrap_or_default(),
+    ];
+
+    if names
+        .iter()
+        .any(|name| HELPER_PATTERNS.iter().any(|pattern| glob(pattern, name)))
+    {
+        return true;
+    }
+
+    if extras_match(node, "hidden=true") || extras_match(node, "visible=false") {
+        return true;
+    }
+
+    // KHR_node_visibility
+    node.extension_value("KHR_node_visibility")
+        .and_then(|value| value.get("visible"))
+        .and_then(Value::as_bool)
+        == Some(false)
+}
+
+/// Matches node extras against `key`, true when the key is present and not
+/// `false` or `null`, or against `key=value`
+fn extras_match(node: &Node<'_>, filter: &str) -> bool {
+    let extras = match node.extras() {
+        Some(extras) => extras,
+        None => return false,
+    };
+
+    let extras: Value = match serde_json::from_str(extras.get()) {
+        Ok(extras) => extras,
+        Err(_) => return false,
+    };
+
+    let (key, expected) = match filter.split_once('=') {
+        Some((key, value)) => (key, Some(value)),
+        None => (filter, None),
+    };
+
+    let value = match extras.get(key) {
+        Some(value) => value,
+        None => return false,
+    };
+
+    match expected {
+        Some(expected) => match value {
+            Value::String(value) => value == expected,
+            value => serde_json::from_str::<Value>(expected).is_ok_and(|e| e == *value),
+        },
+        None => !matches!(value, Value::Null | Value::Bool(false)),
+    }
+}
+
+/// Glob matching supporting `*`, `?` and `[a-z]` character ranges
+fn glob(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<char>>();
+    let text = text.chars().collect::<Vec<char>>();
+
+    glob_from(&pattern, &text)
+}
+
+fn glob_from(pattern: &[char], text: &[char]) -> bool {
+    match pattern.first() {
+        None => text.is_empty(),
+        Some('*') => (0..=text.len()).any(|i| glob_from(&pattern[1..], &text[i..])),
+        Some('?') => !text.is_empty() && glob_from(&pattern[1..], &text[1..]),
+        Some('[') => {
+            let end = match pattern.iter().position(|c| *c == ']') {
+                Some(end) => end,
+                None => {
+                    return !text.is_empty()
+                        && text[0] == '['
+                        && glob_from(&pattern[1..], &text[1..])
+                }
+            };
+
+            let class = &pattern[1..end];
+
+            // Ranges use up their `-` and end, which don't match on their own
+            let matches = |c: char| {
+                let mut i = 0;
+
+                while i < class.len() {
+                    match class.get(i + 1) == Some(&'-') && i + 2 < class.len() {
+                        true => {
+                            if (class[i]..=class[i + 2]).contains(&c) {
+                                return true;
+                            }
+
+                            i += 3;
+                        }
+                        false => {
+                            if class[i] == c {
+                                return true;
+                            }
+
+                            i += 1;
+                        }
+                    }
+                }
+
+                false
+            };
+
+            !text.is_empty() && matches(text[0]) && glob_from(&pattern[end + 1..], &text[1..])
+        }
+        Some(c) => !text.is_empty() && text[0] == *c && glob_from(&pattern[1..], &text[1..]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::glob;
+
+    #[test]
+    fn wildcards() {
+        assert!(glob("wall_*", "wall_lod0"));
+        assert!(glob("*_lod?", "wall_lod0"));
+        assert!(glob("*", ""));
+        assert!(!glob("*_lod?", "wall_lod10"));
+        assert!(!glob("wall", "wall_lod0"));
+    }
+
+    #[test]
+    fn ranges() {
+        assert!(glob("*_lod[1-9]*", "wall_lod1"));
+        assert!(glob("*_lod[1-9]*", "wall_lod9_x"));
+        assert!(!glob("*_lod[1-9]*", "wall_lod0"));
+        assert!(!glob("*_lod[1-9]*", "wall_lod-x"));
+        assert!(glob("[ab-]x", "-x"));
+        assert!(glob("[a-cx]", "x"));
+        assert!(!glob("[a-cx]", "d"));
+    }
+
+    #[test]
+    fn unclosed_bracket() {
+        assert!(glob("lod[1", "lod[1"));
+        assert!(!glob("lod[1", "lod1"));
+    }
+}
@@ -0,0 +1,270 @@
+use std::path::{Path, PathBuf};
+
+use gltf::{buffer::Data, image::Source, mesh::Mode, Document, Node};
+use nalgebra::{Matrix4, Point3, Vector2, Vector3};
+use pose::PoseState;
+
+use crate::mesh::{texture::Texture, Mesh, MeshSource};
+
+pub mod filter;
+pub mod pose;
+
+pub use filter::{NodeFilter, SceneSelection};
+pub use pose::Pose;
+
+/// Options controlling how a glTF file is turned into meshes
+#[derive(Clone, Debug, Default)]
+pub struct GltfOptions {
+    /// Pose in which skinned meshes are evaluated
+    pub pose: Pose,
+    /// Morph weights applied to every morphed mesh, overriding the weights
+    /// stored in the file or sampled from an animation
+    pub morph_weights: Option<Vec<f32>>,
+    /// Scenes to voxelize
+    pub scene: SceneSelection,
+    /// Nodes to keep or drop
+    pub filter: NodeFilter,
+}
+
+pub fn load_gltf<P: AsRef<Path>>(path: P, options: &GltfOptions) -> Vec<Mesh> {
+    GltfFile::open(path).meshes(options)
+}
+
+/// A parsed glTF file, kept around to evaluate it in several poses
+pub struct GltfFile {
+    path: PathBuf,
+    document: Document,
+    buffers: Vec<Data>,
+}
+
+impl GltfFile {
+    pub fn open<P: AsRef<Path>>(path: P) -> Self {
+        let (document, buffers, _) = gltf::import(&path).unwrap();
+
+        Self {
+            path: path.as_ref().to_path_buf(),
+            document,
+            buffers,
+        }
+    }
+
+    /// Duration in seconds of an animation, the first animation of the file
+    /// is used when `name` is `None`
+    pub fn animation_duration(&self, name: Option<&str>) -> f32 {
+        let animation = pose::find_animation(&self.document, name);
+
+        animation
+            .channels()
+            .filter_map(|channel| {
+                channel
+                    .reader(|buffer| Some(&self.buffers[buffer.index()]))
+                    .read_inputs()
+                    .and_then(|inputs| inputs.last())
+            })
+            .fold(0f32, f32::max)
+    }
+
+    pub fn meshes(&self, options: &GltfOptions) -> Vec<Mesh> {
+        let mut meshes = Vec::new();
+
+        for scene in options.scene.scenes(&self.document) {
+            let state = PoseState::evaluate(&self.document, &scene, &self.buffers, &options.pose);
+
+            // Extracting Nodes
+            let mut nodes: Vec<Node<'_>> = Vec::new();
+
+            let mut stack: Vec<(Node<'_>, bool)> = Vec::new();
+
+            let included = options.filter.includes_all();
+
+            stack.extend(scene.nodes().map(|node| (node, included)));
+
+            while let Some((node, included)) = stack.pop() {
+                if options.filter.excludes(&node) {
+                    continue;
+                }
+
+                let included = included || options.filter.includes(&node);
+
+                stack.extend(node.children().map(|child| (child, included)));
+
+                if included {
+                    nodes.push(node);
+                }
+            }
+
+            // Processing Nodes
+            for node in nodes {
+                load_node(
+                    &self.path,
+                    &node,
+                    &self.buffers,
+                    &state,
+                    options,
+                    &mut meshes,
+                );
+            }
+        }
+
+        meshes
+    }
+}
+
+fn load_node(
+    path: &Path,
+    node: &Node<'_>,
+    buffers: &[Data],
+    state: &PoseState,
+    options: &GltfOptions,
+    meshes: &mut Vec<Mesh>,
+) {
+    let mesh = match node.mesh() {
+        Some(m) => m,
+        None => {
+            return;
+        }
+    };
+
+    let weights = options
+        .morph_weights
+        .clone()
+        .or_else(|| state.weights(node));
+
+    let joint_matrices = state.joint_matrices(node, buffers);
+
+    let world = state.world(node);
+
+    for primitive in mesh.primitives() {
+        if primitive.mode() != Mode::Triangles {
+            panic!("Mesh contains invalid primitive mode");
+        }
+
+        let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+
+        let material = primitive.material();
+
+        let pbr = material.pbr_metallic_roughness();
+
+        let mut tmp = None;
+
+        if let Some(texture) = pbr.base_color_texture() {
+            let texture = texture.texture();
+
+            let image = texture.source();
+
+            match image.source() {
+                Source::View { view, mime_type: _ } => {
+                    let buffer = &buffers[view.buffer().index()];
+
+                    let begin = view.offset();
+                    let end = begin + view.length();
+
+                    let data = &buffer[begin..end];
+
+                    tmp = Some(Texture::Raw(data.to_vec()))
+                }
+                Source::Uri { uri, mime_type: _ } => {
+                    let mut path = path.to_path_buf();
+                    path.pop();
+
+                    path.push(uri);
+
+                    tmp = Some(Texture::Path(path));
+                }
+            };
+        }
+
+        let mut vertices = reader
+            .read_positions()
+            .unwrap()
+            .map(|v| Vector3::new(v[0], v[1], v[2]))
+            .collect::<Vec<Vector3<f32>>>();
+
+        // Morph targets displace the vertices before skinning
+        if let Some(weights) = &weights {
+            for (weight, (positions, _, _)) in weights.iter().zip(reader.read_morph_targets()) {
+                if *weight == 0.0 {
+                    continue;
+                }
+
+                if let Some(positions) = positions {
+                    for (vertex, displacement) in vertices.iter_mut().zip(positions) {
+                        *vertex += Vector3::from(displacement) * *weight;
+                    }
+                }
+            }
+        }
+
+        match (
+            &joint_matrices,
+            reader.read_joints(0),
+            reader.read_weights(0),
+        ) {
+            // Skinned vertices are placed by their joints, the node's own
+            // transform doesn't apply to them
+            (Some(joint_matrices), Some(joints), Some(joint_weights)) => {
+                for ((vertex, joints), joint_weights) in vertices
+                    .iter_mut()
+                    .zip(joints.into_u16())
+                    .zip(joint_weights.into_f32())
+                {
+                    let mut matrix = Matrix4::zeros();
+
+                    for (joint, weight) in joints.iter().zip(joint_weights) {
+                        matrix += joint_matrices[*joint as usize] * weight;
+                    }
+
+                    *vertex = transform(&matrix, vertex);
+                }
+            }
+            _ => {
+                for vertex in vertices.iter_mut() {
+                    *vertex = transform(&world, vertex);
+                }
+            }
+        }
+
+        let indices = reader
+            .read_indices()
+            .unwrap()
+            .into_u32()
+            .map(|n| n as usize)
+            .collect::<Vec<usize>>();
+
+        let mut coordinates = None;
+
+        if tmp.is_some() {
+            coordinates = Some(
+                reader
+                    .read_tex_coords(0)
+                    .expect("Got texture but no texture coordinates")
+                    .into_f32()
+                    .map(|t| Vector2::new(t[0], t[1]))
+                    .collect::<Vec<Vector2<f32>>>(),
+            );
+        }
+
+        let source = MeshSource {
+            node: Some(
+                node.name()
+                    .map(str::to_string)
+                    .unwrap_or_else(|| format!("node{}", node.index())),
+            ),
+            node_index: Some(node.index()),
+            material: Some(
+                material
+                    .name()
+                    .map(str::to_string)
+                    .or_else(|| material.index().map(|index| format!("material{}", index)))
+                    .unwrap_or_else(|| "default".to_string()),
+            ),
+            material_index: material.index(),
+        };
+
+        meshes.push(Mesh::new(vertices, indices, coordinates, tmp).with_source(source));
+    }
+}
+
+fn transform(matrix: &Matrix4<f32>, vertex: &Vector3<f32>) -> Vector3<f32> {
+    matrix.transform_point(&Point3::from(*vertex)).coords
+}
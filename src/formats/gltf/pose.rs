@@ -0,0 +1,287 @@
+use ahash::HashMap;
+use gltf::{
+    animation::{util::ReadOutputs, Interpolation},
+    buffer::Data,
+    Animation, Document, Node, Scene,
+};
+use nalgebra::{Matrix4, Quaternion, UnitQuaternion, Vector3, Vector4};
+
+/// Pose in which skinned and morphed meshes are evaluated
+#[derive(Clone, Debug, Default)]
+pub enum Pose {
+    /// Skinning is ignored, vertices are only placed by their node
+    #[default]
+    Bind,
+    /// Skinning evaluated with the nodes' own transforms
+    Rest,
+    /// Skinning evaluated with an animation sampled at `time` seconds, the
+    /// first animation of the file is used when `animation` is `None`
+    Animation {
+        animation: Option<String>,
+        time: f32,
+    },
+}
+
+/// Local transform of a node, split in its components
+#[derive(Clone, Copy, Debug)]
+struct Trs {
+    translation: Vector3<f32>,
+    rotation: UnitQuaternion<f32>,
+    scale: Vector3<f32>,
+}
+
+impl Trs {
+    fn from_node(node: &Node<'_>) -> Self {
+        let (t, r, s) = node.transform().decomposed();
+
+        Self {
+            translation: Vector3::from(t),
+            rotation: UnitQuaternion::from_quaternion(Quaternion::new(r[3], r[0], r[1], r[2])),
+            scale: Vector3::from(s),
+        }
+    }
+
+    fn matrix(&self) -> Matrix4<f32> {
+        Matrix4::new_translation(&self.translation)
+            * self.rotation.to_homogeneous()
+            * Matrix4::new_nonuniform_scaling(&self.scale)
+    }
+}
+
+/// Node transforms and morph weights of a scene evaluated in a given pose
+pub struct PoseState {
+    pub pose: Pose,
+    world: Vec<Matrix4<f32>>,
+    weights: HashMap<usize, Vec<f32>>,
+}
+
+impl PoseState {
+    pub fn evaluate(document: &Document, scene: &Scene<'_>, buffers: &[Data], pose: &Pose) -> Self {
+        let mut locals = document
+            .nodes()
+            .map(|node| Trs::from_node(&node))
+            .collect::<Vec<Trs>>();
+
+        let mut weights = HashMap::default();
+
+        if let Pose::Animation { animation, time } = pose {
+            let animation = find_animation(document, animation.as_deref());
+
+            sample_animation(&animation, buffers, *time, &mut locals, &mut weights);
+        }
+
+        let mut world = vec![Matrix4::identity(); locals.len()];
+
+        let mut stack = scene
+            .nodes()
+            .map(|node| (node, Matrix4::identity()))
+            .collect::<Vec<(Node<'_>, Matrix4<f32>)>>();
+
+        while let Some((node, parent)) = stack.pop() {
+            let matrix = parent * locals[node.index()].matrix();
+
+            world[node.index()] = matrix;
+
+            stack.extend(node.children().map(|child| (child, matrix)));
+        }
+
+        Self {
+            pose: pose.clone(),
+            world,
+            weights,
+        }
+    }
+
+    /// World transform of a node
+    pub fn world(&self, node: &Node<'_>) -> Matrix4<f32> {
+        self.world[node.index()]
+    }
+
+    /// Morph weights of a node, animated weights take priority over the
+    /// node's and the mesh's default weights
+    pub fn weights(&self, node: &Node<'_>) -> Option<Vec<f32>> {
+        if let Some(weights) = self.weights.get(&node.index()) {
+            return Some(weights.clone());
+        }
+
+        node.weights()
+            .or_else(|| node.mesh().and_then(|mesh| mesh.weights()))
+            .map(|weights| weights.to_vec())
+    }
+
+    /// Skinning matrices of a node's skin, `None` when the node isn't skinned
+    /// or when the pose keeps the bind pose
+    pub fn joint_matrices(&self, node: &Node<'_>, buffers: &[Data]) -> Option<Vec<Matrix4<f32>>> {
+        if let Pose::Bind = self.pose {
+            return None;
+        }
+
+        let skin = node.skin()?;
+
+        let reader = skin.reader(|buffer| Some(&buffers[buffer.index()]));
+
+        let inverse_bind = match reader.read_inverse_bind_matrices() {
+            Some(matrices) => matrices
+                .map(|m| Matrix4::from_fn(|row, col| m[col][row]))
+                .collect::<Vec<Matrix4<f32>>>(),
+            None => vec![Matrix4::identity(); skin.joints().count()],
+        };
+
+        Some(
+            skin.joints()
+                .zip(inverse_bind)
+                .map(|(joint, inverse_bind)| self.world[joint.index()] * inverse_bind)
+                .collect(),
+        )
+    }
+}
+
+/// Looks up an animation by name, or the first animation when `name` is `None`
+pub fn find_animation<'a>(document: &'a Document, name: Option<&str>) -> Animation<'a> {
+    match name {
+        Some(name) => document
+            .animations()
+            .find(|a| a.name() == Some(name))
+            .unwrap_or_else(|| panic!("Animation '{}' not found", name)),
+        None => document
+            .animations()
+            .next()
+            .expect("File doesn't contain any animation"),
+    }
+}
+
+fn sample_animation(
+    animation: &Animation<'_>,
+    buffers: &[Data],
+    time: f32,
+    locals: &mut [Trs],
+    weights: &mut HashMap<usize, Vec<f32>>,
+) {
+    for channel in animation.channels() {
+        let reader = channel.reader(|buffer| Some(&buffers[buffer.index()]));
+
+        let inputs = match reader.read_inputs() {
+            Some(inputs) => inputs.collect::<Vec<f32>>(),
+            None => continue,
+        };
+
+        if inputs.is_empty() {
+            continue;
+        }
+
+        let interpolation = channel.sampler().interpolation();
+
+        let node = channel.target().node().index();
+
+        match reader.read_outputs() {
+            Some(ReadOutputs::Translations(outputs)) => {
+                let outputs = outputs.flatten().collect::<Vec<f32>>();
+                let v = sample(&inputs, &outputs, interpolation, time);
+                locals[node].translation = Vector3::new(v[0], v[1], v[2]);
+            }
+            Some(ReadOutputs::Scales(outputs)) => {
+                let outputs = outputs.flatten().collect::<Vec<f32>>();
+                let v = sample(&inputs, &outputs, interpolation, time);
+                locals[node].scale = Vector3::new(v[0], v[1], v[2]);
+            }
+            Some(ReadOutputs::Rotations(outputs)) => {
+                let outputs = outputs.into_f32().flatten().collect::<Vec<f32>>();
+                let v = match interpolation {
+                    // Slerp between keyframes instead of the component-wise lerp
+                    Interpolation::Linear => slerp(&inputs, &outputs, time),
+                    _ => sample(&inputs, &outputs, interpolation, time),
+                };
+                locals[node].rotation = UnitQuaternion::from_quaternion(Quaternion::from(
+                    Vector4::new(v[0], v[1], v[2], v[3]),
+                ));
+            }
+            Some(ReadOutputs::MorphTargetWeights(outputs)) => {
+                let outputs = outputs.into_f32().collect::<Vec<f32>>();
+                weights.insert(node, sample(&inputs, &outputs, interpolation, time));
+            }
+            None => {}
+        }
+    }
+}
+
+/// Finds the keyframe interval containing `time` and the interpolation
+/// factor inside of it, times outside of the animation are clamped and NaN
+/// times are at the start
+fn keyframe(inputs: &[f32], time: f32) -> (usize, usize, f32) {
+    let last = inputs.len().saturating_sub(1);
+
+    if inputs.is_empty() || time.is_nan() || time <= inputs[0] {
+        return (0, 0, 0.0);
+    }
+
+    if time >= inputs[last] {
+        return (last, last, 0.0);
+    }
+
+    // Clamped in case the keyframe times aren't increasing
+    let next = inputs.partition_point(|t| *t <= time).clamp(1, last);
+    let previous = next - 1;
+
+    let factor = (time - inputs[previous]) / (inputs[next] - inputs[previous]);
+
+    (previous, next, factor)
+}
+
+/// Samples a flattened sampler output with `n` components per keyframe
+fn sample(inputs: &[f32], outputs: &[f32], interpolation: Interpolation, time: f32) -> Vec<f32> {
+    let (previous, next, factor) = keyframe(inputs, time);
+
+    match interpolation {
+        Interpolation::Step => {
+            let n = outputs.len() / inputs.len();
+            outputs[previous * n..(previous + 1) * n].to_vec()
+        }
+        Interpolation::Linear => {
+            let n = outputs.len() / inputs.len();
+            let a = &outputs[previous * n..(previous + 1) * n];
+            let b = &outputs[next * n..(next + 1) * n];
+
+            a.iter().zip(b).map(|(a, b)| a + (b - a) * factor).collect()
+        }
+        Interpolation::CubicSpline => {
+            // Keyframes are stored as in-tangent, value, out-tangent
+            let n = outputs.len() / inputs.len() / 3;
+            let value = |k: usize, i: usize| outputs[(k * 3 + 1) * n + i];
+            let in_tangent = |k: usize, i: usize| outputs[k * 3 * n + i];
+            let out_tangent = |k: usize, i: usize| outputs[(k * 3 + 2) * n + i];
+
+            let delta = inputs[next] - inputs[previous];
+            let s = factor;
+            let s2 = s * s;
+            let s3 = s2 * s;
+
+            (0..n)
+                .map(|i| {
+                    (2.0 * s3 - 3.0 * s2 + 1.0) * value(previous, i)
+                        + (s3 - 2.0 * s2 + s) * delta * out_tangent(previous, i)
+                        + (-2.0 * s3 + 3.0 * s2) * value(next, i)
+                        + (s3 - s2) * delta * in_tangent(next, i)
+                })
+                .collect()
+        }
+    }
+}
+
+fn slerp(inputs: &[f32], outputs: &[f32], time: f32) -> Vec<f32> {
+    let (previous, next, factor) = keyframe(inputs, time);
+
+    let quaternion = |k: usize| {
+        UnitQuaternion::from_quaternion(Quaternion::new(
+            outputs[k * 4 + 3],
+            outputs[k * 4],
+            outputs[k * 4 + 1],
+            outputs[k * 4 + 2],
+        ))
+    };
+
+    let q = quaternion(previous)
+        .try_slerp(&quaternion(next), factor, 1e-6)
+        .unwrap_or_else(|| quaternion(previous));
+
+    vec![q.i, q.j, q.k, q.w]
+}
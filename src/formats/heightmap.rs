@@ -0,0 +1,43 @@
+use std::path::Path;
+
+use image::ImageReader;
+
+use crate::terrain::Heightmap;
+
+/// Loads a grayscale heightmap, 8 or 16 bit, the brightest value being
+/// `height_scale` high, and an optional colour map
+pub fn load_heightmap<P: AsRef<Path>>(
+    path: P,
+    pixel_size: f32,
+    height_scale: f32,
+    color_map: Option<&Path>,
+) -> Heightmap {
+    // 8 bit values are widened to 16 bit, the range stays the same
+    let image = ImageReader::open(path.as_ref())
+        .unwrap()
+        .with_guessed_format()
+        .unwrap()
+        .decode()
+        .unwrap()
+        .to_luma16();
+
+    let heights = image
+        .pixels()
+        .map(|p| p.0[0] as f32 / u16::MAX as f32 * height_scale)
+        .collect();
+
+    let heightmap = Heightmap::new(image.width(), image.height(), heights, pixel_size);
+
+    match color_map {
+        Some(path) => heightmap.with_colors(
+            ImageReader::open(path)
+                .unwrap()
+                .with_guessed_format()
+                .unwrap()
+                .decode()
+                .unwrap()
+                .to_rgba8(),
+        ),
+        None => heightmap,
+    }
+}
@@ -0,0 +1,137 @@
+use std::path::Path;
+
+use clap::ValueEnum;
+use las::Reader;
+use nalgebra::Vector3;
+
+use crate::pointcloud::{LocalOrigin, PointCloud};
+
+/// Attribute LAS points are coloured with
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LasColor {
+    /// RGB when the point format has it, intensity otherwise
+    #[default]
+    Rgb,
+    /// Grayscale intensity, normalized by the highest intensity
+    Intensity,
+    /// ASPRS standard classification palette
+    Classification,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct LasOptions {
+    /// Classifications to keep, every class is kept when empty
+    pub classes: Vec<u8>,
+    /// Classifications to drop
+    pub exclude_classes: Vec<u8>,
+    pub color: LasColor,
+}
+
+/// Loads a LAS or LAZ file, the header scale and offset are applied by the
+/// reader. Returns the point cloud and the number of points filtered out by
+/// classification
+pub fn load_las<P: AsRef<Path>>(
+    path: P,
+    options: &LasOptions,
+    origin: &LocalOrigin,
+) -> (PointCloud, usize) {
+    let mut reader = Reader::from_path(path.as_ref()).expect("Invalid las file");
+
+    let data = reader.read_all().expect("Failed to read las points");
+
+    let x = data.x().collect::<Vec<f64>>();
+    let y = data.y().collect::<Vec<f64>>();
+    let z = data.z().collect::<Vec<f64>>();
+    let classes = data.classification().collect::<Vec<u8>>();
+
+    let colors = match (options.color, data.rgb()) {
+        (LasColor::Rgb, Some(rgb)) => rgb_colors(rgb.collect()),
+        (LasColor::Classification, _) => classes.iter().map(|c| class_color(*c)).collect(),
+        _ => intensity_colors(data.intensity().collect()),
+    };
+
+    let mut points = Vec::with_capacity(x.len());
+
+    for i in 0..x.len() {
+        if !options.classes.is_empty() && !options.classes.contains(&classes[i]) {
+            continue;
+        }
+
+        if options.exclude_classes.contains(&classes[i]) {
+            continue;
+        }
+
+        points.push((Vector3::new(x[i], y[i], z[i]), colors[i]));
+    }
+
+    let filtered = x.len() - points.len();
+
+    (PointCloud::from_global(points, origin), filtered)
+}
+
+/// Scales 16 bit colours to 8 bit. The specification asks for 16 bit
+/// values but many writers store 8 bit ones, which are kept as they are
+fn rgb_colors(rgb: Vec<(u16, u16, u16)>) -> Vec<[u8; 4]> {
+    let max = rgb
+        .iter()
+        .map(|(r, g, b)| *r.max(g).max(b))
+        .max()
+        .unwrap_or(0);
+
+    let shift = match max > 255 {
+        true => 8,
+        false => 0,
+    };
+
+    rgb.into_iter()
+        .map(|(r, g, b)| {
+            [
+                (r >> shift) as u8,
+                (g >> shift) as u8,
+                (b >> shift) as u8,
+                255,
+            ]
+        })
+        .collect()
+}
+
+fn intensity_colors(intensity: Vec<u16>) -> Vec<[u8; 4]> {
+    let max = intensity.iter().copied().max().unwrap_or(0).max(1) as f32;
+
+    intensity
+        .into_iter()
+        .map(|i| {
+            let v = (i as f32 / max * 255.0) as u8;
+            [v, v, v, 255]
+        })
+        .collect()
+}
+
+/// Colour of an ASPRS standard class
+fn class_color(class: u8) -> [u8; 4] {
+    match class {
+        // Never classified, unclassified
+        0 | 1 => [170, 170, 170, 255],
+        // Ground
+        2 => [166, 116, 66, 255],
+        // Low, medium and high vegetation
+        3 => [144, 238, 144, 255],
+        4 => [60, 179, 60, 255],
+        5 => [0, 110, 0, 255],
+        // Building
+        6 => [220, 60, 50, 255],
+        // Low and high noise
+        7 | 18 => [255, 0, 255, 255],
+        // Water
+        9 => [50, 110, 230, 255],
+        // Rail
+        10 => [120, 80, 40, 255],
+        // Road surface
+        11 => [70, 70, 70, 255],
+        // Wires and towers
+        13..=16 => [250, 210, 0, 255],
+        // Bridge deck
+        17 => [200, 150, 120, 255],
+        _ => [255, 255, 255, 255],
+    }
+}
@@ -1,3 +1,14 @@
+pub mod colmap;
+pub mod e57;
 pub mod gltf;
+pub mod heightmap;
+pub mod las;
+pub mod pcd;
 pub mod ply;
+pub mod rgbd;
+pub mod sdf;
+pub mod slices;
+pub mod splat;
+pub mod sprite;
+pub mod text;
 pub mod voxels;
@@ -0,0 +1,321 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, Read},
+    path::Path,
+};
+
+use nalgebra::Vector3;
+
+use crate::{
+    formats::ply::Kind,
+    pointcloud::{LocalOrigin, PointCloud},
+};
+
+/// Field of a pcd point
+struct Field {
+    name: String,
+    kind: Kind,
+    count: usize,
+    /// Byte offset in a point record
+    offset: usize,
+}
+
+enum Data {
+    Ascii,
+    Binary,
+    /// Lzf compressed, fields stored one after the other
+    BinaryCompressed,
+}
+
+/// Loads a PCL point cloud, in ascii, binary or binary compressed form. The
+/// viewpoint position, when given, is kept as the sensor origin
+pub fn load_pcd<P: AsRef<Path>>(path: P, origin: &LocalOrigin) -> PointCloud {
+    let mut reader = BufReader::new(File::open(path.as_ref()).unwrap());
+
+    let mut names = Vec::new();
+    let mut sizes = Vec::new();
+    let mut types = Vec::new();
+    let mut counts = Vec::new();
+    let mut points = 0;
+    let mut viewpoint: Option<[f64; 7]> = None;
+
+    let data = loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+
+        let words = line.split_whitespace().collect::<Vec<&str>>();
+
+        match words.as_slice() {
+            [] => panic!("Unexpected end of pcd header"),
+            [comment, ..] if comment.starts_with('#') => {}
+            ["VERSION", ..] | ["WIDTH", ..] | ["HEIGHT", ..] => {}
+            ["FIELDS", fields @ ..] => names = fields.iter().map(|f| f.to_string()).collect(),
+            ["SIZE", values @ ..] => sizes = parse_values::<usize>(values),
+            ["TYPE", values @ ..] => types = values.iter().map(|v| v.to_string()).collect(),
+            ["COUNT", values @ ..] => counts = parse_values::<usize>(values),
+            ["VIEWPOINT", values @ ..] => {
+                viewpoint = Some(
+                    parse_values::<f64>(values)
+                        .try_into()
+                        .expect("Pcd viewpoint expects 7 values"),
+                )
+            }
+            ["POINTS", count] => points = count.parse::<usize>().unwrap(),
+            ["DATA", "ascii"] => break Data::Ascii,
+            ["DATA", "binary"] => break Data::Binary,
+            ["DATA", "binary_compressed"] => break Data::BinaryCompressed,
+            _ => panic!("Invalid pcd header line '{}'", line.trim_end()),
+        }
+    };
+
+    if counts.is_empty() {
+        counts = vec![1; names.len()];
+    }
+
+    assert!(
+        names.len() == sizes.len() && names.len() == types.len() && names.len() == counts.len(),
+        "Pcd FIELDS, SIZE, TYPE and COUNT have different lengths"
+    );
+
+    let mut fields = Vec::with_capacity(names.len());
+    let mut stride = 0;
+
+    for i in 0..names.len() {
+        let kind = match (types[i].as_str(), sizes[i]) {
+            ("I", 1) => Kind::Char,
+            ("U", 1) => Kind::UChar,
+            ("I", 2) => Kind::Short,
+            ("U", 2) => Kind::UShort,
+            ("I", 4) => Kind::Int,
+            ("U", 4) => Kind::UInt,
+            ("F", 4) => Kind::Float,
+            ("F", 8) => Kind::Double,
+            (kind, size) => panic!("Unsupported pcd field type {} of size {}", kind, size),
+        };
+
+        fields.push(Field {
+            name: names[i].clone(),
+            kind,
+            count: counts[i],
+            offset: stride,
+        });
+
+        stride += kind.size() * counts[i];
+    }
+
+    // Point records, interleaved
+    let records = match data {
+        Data::Ascii => read_ascii(&mut reader, &fields, stride, points),
+        Data::Binary => {
+            let mut records = vec![0u8; stride * points];
+            reader.read_exact(&mut records).unwrap();
+            records
+        }
+        Data::BinaryCompressed => {
+            let mut sizes = [0u8; 8];
+            reader.read_exact(&mut sizes).unwrap();
+
+            let compressed = u32::from_le_bytes(sizes[..4].try_into().unwrap()) as usize;
+            let uncompressed = u32::from_le_bytes(sizes[4..].try_into().unwrap()) as usize;
+
+            let mut buffer = vec![0u8; compressed];
+            reader.read_exact(&mut buffer).unwrap();
+
+            let columns = lzf_decompress(&buffer, uncompressed);
+            interleave(&columns, &fields, stride, points)
+        }
+    };
+
+    let field = |name: &str| fields.iter().find(|f| f.name == name);
+
+    let x = field("x").expect("Pcd points have no x");
+    let y = field("y").expect("Pcd points have no y");
+    let z = field("z").expect("Pcd points have no z");
+    let color = field("rgb").or_else(|| field("rgba"));
+    let intensity = field("intensity");
+
+    let max_intensity = intensity.map(|intensity| {
+        records
+            .chunks_exact(stride)
+            .map(|r| intensity.kind.read(&r[intensity.offset..]))
+            .fold(f64::EPSILON, f64::max)
+    });
+
+    let mut buffer = Vec::with_capacity(points);
+
+    for record in records.chunks_exact(stride) {
+        let position = Vector3::new(
+            x.kind.read(&record[x.offset..]),
+            y.kind.read(&record[y.offset..]),
+            z.kind.read(&record[z.offset..]),
+        );
+
+        if position.iter().any(|v| v.is_nan()) {
+            continue;
+        }
+
+        let color = match (color, intensity) {
+            // Packed 0x00RRGGBB, stored as a float or an unsigned int
+            (Some(color), _) => {
+                let [b, g, r, _] = record[color.offset..color.offset + 4].try_into().unwrap();
+                [r, g, b, 255]
+            }
+            (None, Some(intensity)) => {
+                let v = intensity.kind.read(&record[intensity.offset..]) / max_intensity.unwrap()
+                    * 255.0;
+                [v as u8, v as u8, v as u8, 255]
+            }
+            (None, None) => [255u8; 4],
+        };
+
+        buffer.push((position, color));
+    }
+
+    let mut pointcloud = PointCloud::from_global(buffer, origin);
+
+    if let Some(viewpoint) = viewpoint {
+        pointcloud.set_sensor_origin(Vector3::new(viewpoint[0], viewpoint[1], viewpoint[2]));
+    }
+
+    pointcloud
+}
+
+fn parse_values<T: std::str::FromStr>(values: &[&str]) -> Vec<T> {
+    values
+        .iter()
+        .map(|v| {
+            v.parse::<T>()
+                .unwrap_or_else(|_| panic!("Invalid pcd header value '{}'", v))
+        })
+        .collect()
+}
+
+/// Converts ascii points to little endian records
+fn read_ascii<R: BufRead>(
+    reader: &mut R,
+    fields: &[Field],
+    stride: usize,
+    points: usize,
+) -> Vec<u8> {
+    let mut records = Vec::with_capacity(stride * points);
+
+    for line in reader.lines() {
+        let line = line.unwrap();
+        let mut values = line.split_whitespace();
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        for field in fields {
+            for _ in 0..field.count {
+                let value = values.next().expect("Missing pcd value");
+
+                match field.kind {
+                    Kind::Char => records.extend(value.parse::<i8>().unwrap().to_le_bytes()),
+                    Kind::UChar => records.extend(value.parse::<u8>().unwrap().to_le_bytes()),
+                    Kind::Short => records.extend(value.parse::<i16>().unwrap().to_le_bytes()),
+                    Kind::UShort => records.extend(value.parse::<u16>().unwrap().to_le_bytes()),
+                    Kind::Int => records.extend(value.parse::<i32>().unwrap().to_le_bytes()),
+                    Kind::UInt => records.extend(value.parse::<u32>().unwrap().to_le_bytes()),
+                    Kind::Float => records.extend(value.parse::<f32>().unwrap().to_le_bytes()),
+                    Kind::Double => records.extend(value.parse::<f64>().unwrap().to_le_bytes()),
+                }
+            }
+        }
+    }
+
+    records
+}
+
+/// Converts fields stored one after the other to interleaved records
+fn interleave(columns: &[u8], fields: &[Field], stride: usize, points: usize) -> Vec<u8> {
+    let mut records = vec![0u8; stride * points];
+    let mut start = 0;
+
+    for field in fields {
+        let size = field.kind.size() * field.count;
+
+        for i in 0..points {
+            records[i * stride + field.offset..i * stride + field.offset + size]
+                .copy_from_slice(&columns[start + i * size..start + (i + 1) * size]);
+        }
+
+        start += size * points;
+    }
+
+    records
+}
+
+/// Decompresses lzf data as written by liblzf
+fn lzf_decompress(input: &[u8], size: usize) -> Vec<u8> {
+    let mut output = Vec::with_capacity(size);
+    let mut i = 0;
+
+    while i < input.len() {
+        let control = input[i] as usize;
+        i += 1;
+
+        match control < 32 {
+            // Literal run of control + 1 bytes
+            true => {
+                output.extend_from_slice(&input[i..i + control + 1]);
+                i += control + 1;
+            }
+            // Back reference
+            false => {
+                let mut length = control >> 5;
+
+                if length == 7 {
+                    length += input[i] as usize;
+                    i += 1;
+                }
+
+                let distance = ((control & 0x1f) << 8) + input[i] as usize + 1;
+                i += 1;
+
+                let start = output.len() - distance;
+
+                // The reference can overlap the bytes it produces
+                for j in 0..length + 2 {
+                    output.push(output[start + j]);
+                }
+            }
+        }
+    }
+
+    assert_eq!(output.len(), size, "Invalid pcd compressed data");
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::lzf_decompress;
+
+    #[test]
+    fn literal_run() {
+        assert_eq!(lzf_decompress(&[2, b'a', b'b', b'c'], 3), b"abc");
+    }
+
+    #[test]
+    fn overlapping_reference() {
+        // 6 bytes from 3 back, overlapping the bytes they produce
+        assert_eq!(
+            lzf_decompress(&[2, b'a', b'b', b'c', 4 << 5, 2], 9),
+            b"abcabcabc"
+        );
+    }
+
+    #[test]
+    fn long_reference() {
+        // 20 bytes from 1 back, the length continued in an extra byte
+        assert_eq!(lzf_decompress(&[0, b'a', 7 << 5, 11, 0], 21), [b'a'; 21]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid pcd compressed data")]
+    fn wrong_size() {
+        lzf_decompress(&[2, b'a', b'b', b'c'], 4);
+    }
+}
@@ -1,77 +1,363 @@
 use std::{
     fs::File,
-    io::{BufRead, BufReader, Read},
+    io::{BufRead, BufReader, BufWriter, Read, Write},
     path::Path,
 };
 
 use nalgebra::Vector3;
 
-use crate::pointcloud::PointCloud;
+use crate::{
+    formats::voxels::{Channels, Metadata},
+    pointcloud::{LocalOrigin, PointCloud},
+};
 
-pub fn load_ply<P: AsRef<Path>>(path: P) -> PointCloud {
-    let mut reader = BufReader::new(File::open(path.as_ref()).unwrap());
+/// Scalar type of a ply property
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+    Char,
+    UChar,
+    Short,
+    UShort,
+    Int,
+    UInt,
+    Float,
+    Double,
+}
+
+impl Kind {
+    fn parse(name: &str) -> Self {
+        match name {
+            "char" | "int8" => Kind::Char,
+            "uchar" | "uint8" => Kind::UChar,
+            "short" | "int16" => Kind::Short,
+            "ushort" | "uint16" => Kind::UShort,
+            "int" | "int32" => Kind::Int,
+            "uint" | "uint32" => Kind::UInt,
+            "float" | "float32" => Kind::Float,
+            "double" | "float64" => Kind::Double,
+            _ => panic!("Unsupported ply property type '{}'", name),
+        }
+    }
+
+    pub fn size(self) -> usize {
+        match self {
+            Kind::Char | Kind::UChar => 1,
+            Kind::Short | Kind::UShort => 2,
+            Kind::Int | Kind::UInt | Kind::Float => 4,
+            Kind::Double => 8,
+        }
+    }
+
+    /// Reads a little endian value
+    pub fn read(self, bytes: &[u8]) -> f64 {
+        match self {
+            Kind::Char => bytes[0] as i8 as f64,
+            Kind::UChar => bytes[0] as f64,
+            Kind::Short => i16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+            Kind::UShort => u16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+            Kind::Int => i32::from_le_bytes(bytes[..4].try_into().unwrap()) as f64,
+            Kind::UInt => u32::from_le_bytes(bytes[..4].try_into().unwrap()) as f64,
+            Kind::Float => f32::from_le_bytes(bytes[..4].try_into().unwrap()) as f64,
+            Kind::Double => f64::from_le_bytes(bytes[..8].try_into().unwrap()),
+        }
+    }
+}
+
+/// Properties of the vertex element of a ply file
+#[derive(Clone, Debug)]
+pub struct VertexLayout {
+    pub count: usize,
+    /// Name, type and byte offset in the vertex record of every property
+    pub properties: Vec<(String, Kind, usize)>,
+    /// Size of a vertex record in bytes
+    pub stride: usize,
+    /// Header comments
+    pub comments: Vec<String>,
+}
+
+impl VertexLayout {
+    /// Type and byte offset of a property
+    pub fn property(&self, name: &str) -> Option<(Kind, usize)> {
+        self.properties
+            .iter()
+            .find(|(n, _, _)| n == name)
+            .map(|(_, kind, offset)| (*kind, *offset))
+    }
+
+    /// Reads a property of a vertex record
+    pub fn read(&self, record: &[u8], property: (Kind, usize)) -> f64 {
+        property.0.read(&record[property.1..])
+    }
+}
 
+/// Reads a binary little endian ply header, the vertex element must come
+/// first so its records can be read right after the header
+pub fn read_header<R: BufRead>(reader: &mut R) -> VertexLayout {
     let mut line = String::new();
 
     reader
         .read_line(&mut line)
         .expect("Invalid ply magic number");
-    assert_eq!(line, "ply\n");
+    assert_eq!(line.trim_end(), "ply");
     line.clear();
 
     reader
         .read_line(&mut line)
         .expect("Invalid ply format, only binary_little_endian is supported");
-    assert_eq!(line, "format binary_little_endian 1.0\n");
+    assert_eq!(line.trim_end(), "format binary_little_endian 1.0");
     line.clear();
 
-    reader.read_line(&mut line).unwrap();
+    let mut layout = VertexLayout {
+        count: 0,
+        properties: Vec::new(),
+        stride: 0,
+        comments: Vec::new(),
+    };
 
-    let num_vertex = line
-        .strip_prefix("element vertex ")
-        .unwrap()
-        .strip_suffix("\n")
-        .unwrap()
-        .parse::<usize>()
-        .unwrap();
+    // Whether the element being declared is the vertex element
+    let mut vertex = false;
 
-    line.clear();
+    loop {
+        line.clear();
+        reader.read_line(&mut line).unwrap();
 
-    reader.read_line(&mut line).unwrap();
-    assert_eq!(line, "property float x\n");
-    line.clear();
+        let words = line.split_whitespace().collect::<Vec<&str>>();
 
-    reader.read_line(&mut line).unwrap();
-    assert_eq!(line, "property float y\n");
-    line.clear();
+        match words.as_slice() {
+            ["end_header"] => break,
+            ["comment", ..] | ["obj_info", ..] => {
+                layout.comments.push(words[1..].join(" "));
+            }
+            ["element", "vertex", count] => {
+                assert!(
+                    layout.properties.is_empty() && !vertex,
+                    "Only one vertex element is supported"
+                );
+                layout.count = count.parse::<usize>().unwrap();
+                vertex = true;
+            }
+            ["element", ..] => {
+                assert!(vertex, "The vertex element must be the first element");
+                vertex = false;
+            }
+            ["property", "list", ..] => {
+                assert!(!vertex, "List properties aren't supported in vertices");
+            }
+            ["property", kind, name] if vertex => {
+                let kind = Kind::parse(kind);
+                layout
+                    .properties
+                    .push((name.to_string(), kind, layout.stride));
+                layout.stride += kind.size();
+            }
+            ["property", ..] => {}
+            [] => panic!("Unexpected end of ply header"),
+            _ => panic!("Invalid ply header line '{}'", line.trim_end()),
+        }
+    }
 
-    reader.read_line(&mut line).unwrap();
-    assert_eq!(line, "property float z\n");
-    line.clear();
+    layout
+}
+
+/// Loads the vertices of a ply file, only those whose entry in `keep` is
+/// true when given, indexed like the vertices of the file
+pub fn load_ply<P: AsRef<Path>>(
+    path: P,
+    origin: &LocalOrigin,
+    keep: Option<&[bool]>,
+) -> PointCloud {
+    let mut reader = BufReader::new(File::open(path.as_ref()).unwrap());
 
-    reader.read_line(&mut line).unwrap();
+    let layout = read_header(&mut reader);
 
-    assert_eq!(line, "end_header\n");
+    if let Some(keep) = keep {
+        assert_eq!(
+            keep.len(),
+            layout.count,
+            "Ply has {} vertices but {} are filtered",
+            layout.count,
+            keep.len()
+        );
+    }
+
+    let x = layout.property("x").expect("Ply vertices have no x");
+    let y = layout.property("y").expect("Ply vertices have no y");
+    let z = layout.property("z").expect("Ply vertices have no z");
+
+    let color = match (
+        layout.property("red"),
+        layout.property("green"),
+        layout.property("blue"),
+    ) {
+        (Some(r), Some(g), Some(b)) => Some((r, g, b, layout.property("alpha"))),
+        _ => None,
+    };
+
+    // Per point sensor positions, used to carve free space
+    let sensor = match (
+        layout.property("sensor_x"),
+        layout.property("sensor_y"),
+        layout.property("sensor_z"),
+    ) {
+        (Some(x), Some(y), Some(z)) => Some((x, y, z)),
+        _ => None,
+    };
+
+    let mut buffer: Vec<(Vector3<f64>, [u8; 4])> = Vec::with_capacity(layout.count);
+    let mut sensors = Vec::new();
 
-    let mut buffer: Vec<(Vector3<f32>, [u8; 4])> = Vec::with_capacity(num_vertex);
+    let mut record = vec![0u8; layout.stride];
 
-    let mut tmp = [0u8; 4];
+    for i in 0..layout.count {
+        reader.read_exact(&mut record).unwrap();
 
-    for _ in 0..num_vertex {
-        reader.read_exact(&mut tmp).unwrap();
-        let x = f32::from_le_bytes(tmp);
-        reader.read_exact(&mut tmp).unwrap();
-        let y = f32::from_le_bytes(tmp);
-        reader.read_exact(&mut tmp).unwrap();
-        let z = f32::from_le_bytes(tmp);
+        if keep.is_some_and(|keep| !keep[i]) {
+            continue;
+        }
+
+        let x = layout.read(&record, x);
+        let y = layout.read(&record, y);
+        let z = layout.read(&record, z);
 
         if x.is_nan() || y.is_nan() || z.is_nan() {
             continue;
         }
 
-        // Swap because the point clouds will be flipped
-        buffer.push((Vector3::new(x, z, y), [255u8; 4]));
+        let color = match color {
+            Some((r, g, b, a)) => [
+                read_color(&layout, &record, r),
+                read_color(&layout, &record, g),
+                read_color(&layout, &record, b),
+                a.map_or(255, |a| read_color(&layout, &record, a)),
+            ],
+            None => [255u8; 4],
+        };
+
+        buffer.push((Vector3::new(x, y, z), color));
+
+        if let Some((x, y, z)) = sensor {
+            sensors.push(Vector3::new(
+                layout.read(&record, x),
+                layout.read(&record, y),
+                layout.read(&record, z),
+            ));
+        }
+    }
+
+    let mut pointcloud = PointCloud::from_global(buffer, origin);
+
+    if sensor.is_some() {
+        pointcloud.set_sensors(sensors);
+    }
+
+    pointcloud
+}
+
+/// Reads an 8 bit colour channel, 16 bit channels are scaled down and
+/// floating point ones are expected between 0 and 1
+fn read_color(layout: &VertexLayout, record: &[u8], property: (Kind, usize)) -> u8 {
+    let value = layout.read(record, property);
+
+    match property.0 {
+        Kind::UShort => (value / 257.0) as u8,
+        Kind::Float | Kind::Double => (value * 255.0).clamp(0.0, 255.0) as u8,
+        _ => value.clamp(0.0, 255.0) as u8,
+    }
+}
+
+/// Saves voxels as a binary ply point cloud with integer coordinates, along
+/// with the channels and metadata the voxel format can't store
+pub fn save_voxels_ply<P: AsRef<Path>>(
+    path: P,
+    voxels: &[(Vector3<i32>, [u8; 4])],
+    channels: &Channels,
+    metadata: &Metadata,
+) {
+    let mut writer = BufWriter::new(File::create(path).unwrap());
+
+    writeln!(writer, "ply").unwrap();
+    writeln!(writer, "format binary_little_endian 1.0").unwrap();
+
+    let size = metadata.voxel_size;
+    writeln!(
+        writer,
+        "comment voxel_size {} {} {}",
+        size.x, size.y, size.z
+    )
+    .unwrap();
+
+    let origin = metadata.origin;
+    writeln!(
+        writer,
+        "comment origin {} {} {}",
+        origin.x, origin.y, origin.z
+    )
+    .unwrap();
+
+    let offset = metadata.offset;
+    if offset != Vector3::zeros() {
+        writeln!(
+            writer,
+            "comment global_offset {} {} {}",
+            offset.x, offset.y, offset.z
+        )
+        .unwrap();
+    }
+
+    for (index, name) in &channels.material_names {
+        // A line break would end the comment and corrupt the header
+        let name = name.chars().filter(|c| !c.is_control()).collect::<String>();
+
+        writeln!(writer, "comment material {} {}", index, name).unwrap();
     }
 
-    PointCloud::new(buffer)
+    writeln!(writer, "element vertex {}", voxels.len()).unwrap();
+    writeln!(writer, "property int x").unwrap();
+    writeln!(writer, "property int y").unwrap();
+    writeln!(writer, "property int z").unwrap();
+    writeln!(writer, "property uchar red").unwrap();
+    writeln!(writer, "property uchar green").unwrap();
+    writeln!(writer, "property uchar blue").unwrap();
+    writeln!(writer, "property uchar alpha").unwrap();
+
+    if channels.materials.is_some() {
+        writeln!(writer, "property uint material").unwrap();
+    }
+
+    if channels.counts.is_some() {
+        writeln!(writer, "property uint count").unwrap();
+    }
+
+    if channels.occupancy.is_some() {
+        writeln!(writer, "property float occupancy").unwrap();
+    }
+
+    if channels.states.is_some() {
+        writeln!(writer, "property uchar state").unwrap();
+    }
+
+    writeln!(writer, "end_header").unwrap();
+
+    for (i, (v, c)) in voxels.iter().enumerate() {
+        writer.write_all(&v.x.to_le_bytes()).unwrap();
+        writer.write_all(&v.y.to_le_bytes()).unwrap();
+        writer.write_all(&v.z.to_le_bytes()).unwrap();
+        writer.write_all(c).unwrap();
+
+        if let Some(materials) = &channels.materials {
+            writer.write_all(&materials[i].to_le_bytes()).unwrap();
+        }
+
+        if let Some(counts) = &channels.counts {
+            writer.write_all(&counts[i].to_le_bytes()).unwrap();
+        }
+
+        if let Some(occupancy) = &channels.occupancy {
+            writer.write_all(&occupancy[i].to_le_bytes()).unwrap();
+        }
+
+        if let Some(states) = &channels.states {
+            writer.write_all(&[states[i]]).unwrap();
+        }
+    }
 }
@@ -0,0 +1,180 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader},
+    path::{Path, PathBuf},
+};
+
+use image::{DynamicImage, ImageReader, RgbaImage};
+use nalgebra::{Matrix4, Quaternion, Translation3, UnitQuaternion, Vector3};
+
+/// Pinhole camera intrinsics in pixels
+#[derive(Clone, Copy, Debug)]
+pub struct Intrinsics {
+    pub fx: f32,
+    pub fy: f32,
+    pub cx: f32,
+    pub cy: f32,
+}
+
+impl Intrinsics {
+    /// Camera space position of a pixel at a depth, x right, y down and z
+    /// forward
+    pub fn unproject(&self, u: f32, v: f32, depth: f32) -> Vector3<f32> {
+        Vector3::new(
+            (u - self.cx) / self.fx * depth,
+            (v - self.cy) / self.fy * depth,
+            depth,
+        )
+    }
+
+    /// Pixel coordinates of a camera space position
+    pub fn project(&self, position: &Vector3<f32>) -> (f32, f32) {
+        (
+            position.x / position.z * self.fx + self.cx,
+            position.y / position.z * self.fy + self.cy,
+        )
+    }
+}
+
+/// Depth map in meters, 0 where the depth is unknown
+pub struct DepthImage {
+    pub width: u32,
+    pub height: u32,
+    pub depths: Vec<f32>,
+}
+
+impl DepthImage {
+    pub fn get(&self, x: u32, y: u32) -> f32 {
+        self.depths[(y * self.width + x) as usize]
+    }
+}
+
+pub struct RgbdFrame {
+    pub depth: PathBuf,
+    pub color: Option<PathBuf>,
+    /// Camera to world transform
+    pub pose: Matrix4<f32>,
+}
+
+impl RgbdFrame {
+    /// Loads the 16 bit depth map, converted to meters
+    pub fn load_depth(&self, depth_scale: f32) -> DepthImage {
+        let depth = match open(&self.depth) {
+            DynamicImage::ImageLuma16(image) => image,
+            _ => panic!(
+                "Depth image '{}' should be a 16 bit grayscale image",
+                self.depth.display()
+            ),
+        };
+
+        DepthImage {
+            width: depth.width(),
+            height: depth.height(),
+            depths: depth
+                .into_raw()
+                .into_iter()
+                .map(|d| d as f32 / depth_scale)
+                .collect(),
+        }
+    }
+
+    /// Loads the colour image, registered to the depth map
+    pub fn load_color(&self, depth: &DepthImage) -> Option<RgbaImage> {
+        self.color.as_ref().map(|path| {
+            let color = open(path).to_rgba8();
+
+            assert!(
+                color.dimensions() == (depth.width, depth.height),
+                "Colour image '{}' and its depth image have different sizes",
+                path.display()
+            );
+
+            color
+        })
+    }
+}
+
+fn open(path: &Path) -> DynamicImage {
+    ImageReader::open(path)
+        .unwrap()
+        .with_guessed_format()
+        .unwrap()
+        .decode()
+        .unwrap()
+}
+
+/// RGB-D capture described by a text file:
+///
+/// ```text
+/// intrinsics <fx> <fy> <cx> <cy>
+/// depth_scale <depth units per meter, 1000 by default>
+/// frame <depth.png> <color.png or -> <tx> <ty> <tz> <qx> <qy> <qz> <qw>
+/// ```
+///
+/// Poses take the camera to the world, image paths are relative to the file
+pub struct RgbdSequence {
+    pub intrinsics: Intrinsics,
+    pub depth_scale: f32,
+    pub frames: Vec<RgbdFrame>,
+}
+
+pub fn load_rgbd<P: AsRef<Path>>(path: P) -> RgbdSequence {
+    let path = path.as_ref();
+    let directory = path.parent().unwrap_or(Path::new(""));
+
+    let reader = BufReader::new(File::open(path).unwrap());
+
+    let mut intrinsics = None;
+    let mut depth_scale = 1000.0;
+    let mut frames = Vec::new();
+
+    for line in reader.lines() {
+        let line = line.unwrap();
+        let line = line.split('#').next().unwrap().trim();
+
+        let words = line.split_whitespace().collect::<Vec<&str>>();
+
+        let number = |value: &str| {
+            value
+                .parse::<f32>()
+                .unwrap_or_else(|_| panic!("Invalid number '{}' in rgbd line '{}'", value, line))
+        };
+
+        match words.as_slice() {
+            [] => {}
+            ["intrinsics", fx, fy, cx, cy] => {
+                intrinsics = Some(Intrinsics {
+                    fx: number(fx),
+                    fy: number(fy),
+                    cx: number(cx),
+                    cy: number(cy),
+                });
+            }
+            ["depth_scale", scale] => depth_scale = number(scale),
+            ["frame", depth, color, pose @ ..] if pose.len() == 7 => {
+                let pose = pose.iter().map(|v| number(v)).collect::<Vec<f32>>();
+
+                let rotation = UnitQuaternion::from_quaternion(Quaternion::new(
+                    pose[6], pose[3], pose[4], pose[5],
+                ));
+                let translation = Translation3::new(pose[0], pose[1], pose[2]);
+
+                frames.push(RgbdFrame {
+                    depth: directory.join(depth),
+                    color: match *color {
+                        "-" => None,
+                        color => Some(directory.join(color)),
+                    },
+                    pose: translation.to_homogeneous() * rotation.to_homogeneous(),
+                });
+            }
+            _ => panic!("Invalid rgbd line '{}'", line),
+        }
+    }
+
+    RgbdSequence {
+        intrinsics: intrinsics.expect("Rgbd file has no intrinsics"),
+        depth_scale,
+        frames,
+    }
+}
@@ -0,0 +1,298 @@
+use std::{fs, iter::Peekable, path::Path};
+
+use nalgebra::Vector3;
+use serde_json::Value;
+
+use crate::sdf::{Operation, Scene, Sdf};
+
+/// Parsed s-expression
+enum Expression {
+    Atom(String),
+    List(Vec<Expression>),
+}
+
+/// Loads a signed distance scene, written as s-expressions. Shapes at the
+/// top level are merged by union:
+///
+/// ```text
+/// # comment
+/// (sphere <x> <y> <z> <radius>)
+/// (box <x> <y> <z> <half x> <half y> <half z>)
+/// (capsule <ax> <ay> <az> <bx> <by> <bz> <radius>)
+/// (torus <x> <y> <z> <major radius> <minor radius>)
+/// (cylinder <ax> <ay> <az> <bx> <by> <bz> <radius>)
+/// (union <shape>...) (subtract <shape>...) (intersect <shape>...)
+/// (smooth-union <k> <shape>...) and the other smooth operations
+/// (color <r> <g> <b> <shape>)
+/// ```
+///
+/// Colours are 0 to 255. Scenes can also be a JSON array of shapes, in `.sdf`
+/// or `.json` files, each an array of the same name and arguments:
+///
+/// ```text
+/// [["sphere", 0, 0, 0, 10], ["color", 255, 0, 0, ["box", 0, 0, 8, 4, 4, 4]]]
+/// ```
+pub fn load_sdf<P: AsRef<Path>>(path: P) -> Scene {
+    let path = path.as_ref();
+    let source = fs::read_to_string(path).unwrap();
+
+    let json = path
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("json"));
+
+    let shapes = read_shapes(&source, json || source.trim_start().starts_with('['));
+
+    assert!(!shapes.is_empty(), "Sdf scene has no shapes");
+
+    Scene::new(Sdf::Combine {
+        operation: Operation::Union,
+        smoothness: 0.0,
+        children: shapes,
+    })
+}
+
+/// Top level shapes of a scene, in JSON or as s-expressions
+fn read_shapes(source: &str, json: bool) -> Vec<Sdf> {
+    match json {
+        true => {
+            let json: Value = serde_json::from_str(source).expect("Invalid JSON sdf scene");
+
+            match json {
+                Value::Array(values) => values.iter().map(|v| shape(&from_json(v))).collect(),
+                _ => panic!("JSON sdf scene isn't an array of shapes"),
+            }
+        }
+        false => {
+            let tokens = tokenize(source);
+            let mut tokens = tokens.iter().map(String::as_str).peekable();
+
+            let mut shapes = Vec::new();
+
+            while tokens.peek().is_some() {
+                shapes.push(shape(&parse(&mut tokens)));
+            }
+
+            shapes
+        }
+    }
+}
+
+fn tokenize(source: &str) -> Vec<String> {
+    source
+        .lines()
+        .map(|line| line.split('#').next().unwrap())
+        .flat_map(|line| {
+            line.replace('(', " ( ")
+                .replace(')', " ) ")
+                .split_whitespace()
+                .map(str::to_string)
+                .collect::<Vec<String>>()
+        })
+        .collect()
+}
+
+fn parse<'a>(tokens: &mut Peekable<impl Iterator<Item = &'a str>>) -> Expression {
+    match tokens.next() {
+        Some("(") => {
+            let mut list = Vec::new();
+
+            loop {
+                match tokens.peek() {
+                    Some(&")") => {
+                        tokens.next();
+                        break Expression::List(list);
+                    }
+                    Some(_) => list.push(parse(tokens)),
+                    None => panic!("Unclosed '(' in sdf scene"),
+                }
+            }
+        }
+        Some(")") => panic!("Unexpected ')' in sdf scene"),
+        Some(atom) => Expression::Atom(atom.to_string()),
+        None => panic!("Unexpected end of sdf scene"),
+    }
+}
+
+/// Expression of a JSON shape, arrays are lists
+fn from_json(value: &Value) -> Expression {
+    match value {
+        Value::Array(values) => Expression::List(values.iter().map(from_json).collect()),
+        Value::String(atom) => Expression::Atom(atom.clone()),
+        Value::Number(number) => Expression::Atom(number.to_string()),
+        other => panic!("Unexpected '{}' in JSON sdf scene", other),
+    }
+}
+
+fn shape(expression: &Expression) -> Sdf {
+    let list = match expression {
+        Expression::List(list) => list,
+        Expression::Atom(atom) => panic!("Expected a shape in sdf scene, found '{}'", atom),
+    };
+
+    let name = match list.first() {
+        Some(Expression::Atom(name)) => name.as_str(),
+        _ => panic!("Sdf scene shape has no name"),
+    };
+
+    let arguments = &list[1..];
+
+    // Leading numbers of the shape
+    let numbers =
+        arguments
+            .iter()
+            .map_while(|argument| match argument {
+                Expression::Atom(atom) => Some(atom.parse::<f32>().unwrap_or_else(|_| {
+                    panic!("Invalid number '{}' in sdf scene '{}'", atom, name)
+                })),
+                Expression::List(_) => None,
+            })
+            .collect::<Vec<f32>>();
+
+    let children = arguments[numbers.len()..]
+        .iter()
+        .map(shape)
+        .collect::<Vec<Sdf>>();
+
+    let vector = |i: usize| Vector3::new(numbers[i], numbers[i + 1], numbers[i + 2]);
+
+    let expect = |count: usize, shapes: usize| {
+        assert!(
+            numbers.len() == count && children.len() == shapes,
+            "Sdf scene '{}' expects {} numbers and {} shapes",
+            name,
+            count,
+            shapes
+        );
+    };
+
+    match name {
+        "sphere" => {
+            expect(4, 0);
+            Sdf::Sphere {
+                center: vector(0),
+                radius: numbers[3],
+            }
+        }
+        "box" => {
+            expect(6, 0);
+            Sdf::Box {
+                center: vector(0),
+                extent: vector(3),
+            }
+        }
+        "capsule" => {
+            expect(7, 0);
+            Sdf::Capsule {
+                a: vector(0),
+                b: vector(3),
+                radius: numbers[6],
+            }
+        }
+        "torus" => {
+            expect(5, 0);
+            Sdf::Torus {
+                center: vector(0),
+                major: numbers[3],
+                minor: numbers[4],
+            }
+        }
+        "cylinder" => {
+            expect(7, 0);
+            Sdf::Cylinder {
+                a: vector(0),
+                b: vector(3),
+                radius: numbers[6],
+            }
+        }
+        "color" => {
+            expect(3, 1);
+            Sdf::Color {
+                color: vector(0),
+                child: Box::new(children.into_iter().next().unwrap()),
+            }
+        }
+        _ => {
+            let (operation, smooth) = match name {
+                "union" => (Operation::Union, false),
+                "subtract" => (Operation::Subtract, false),
+                "intersect" => (Operation::Intersect, false),
+                "smooth-union" => (Operation::Union, true),
+                "smooth-subtract" => (Operation::Subtract, true),
+                "smooth-intersect" => (Operation::Intersect, true),
+                _ => panic!("Unknown sdf scene shape '{}'", name),
+            };
+
+            assert!(
+                numbers.len() == smooth as usize && !children.is_empty(),
+                "Sdf scene '{}' expects {} numbers and at least a shape",
+                name,
+                smooth as usize
+            );
+
+            Sdf::Combine {
+                operation,
+                smoothness: numbers.first().copied().unwrap_or(0.0),
+                children,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::read_shapes;
+    use crate::sdf::{Operation, Sdf};
+
+    #[test]
+    fn nested_shapes() {
+        let shapes = read_shapes(
+            "# comment\n(smooth-union 0.5 (sphere 0 0 0 1) # inline\n (box 1 2 3 4 5 6))",
+            false,
+        );
+
+        match shapes.as_slice() {
+            [Sdf::Combine {
+                operation: Operation::Union,
+                smoothness,
+                children,
+            }] => {
+                assert_eq!(*smoothness, 0.5);
+                assert!(matches!(
+                    children.as_slice(),
+                    [Sdf::Sphere { .. }, Sdf::Box { .. }]
+                ));
+            }
+            other => panic!("Unexpected shapes {:?}", other),
+        }
+    }
+
+    #[test]
+    fn json_matches_s_expressions() {
+        let json = read_shapes(
+            r#"[["sphere", 0, 0, 0, 10], ["color", 255, 0, 0, ["box", 0, 0, 8, 4, 4, 4]]]"#,
+            true,
+        );
+        let s_expressions =
+            read_shapes("(sphere 0 0 0 10) (color 255 0 0 (box 0 0 8 4 4 4))", false);
+
+        assert_eq!(format!("{:?}", json), format!("{:?}", s_expressions));
+    }
+
+    #[test]
+    #[should_panic(expected = "Unclosed '(' in sdf scene")]
+    fn unclosed_list() {
+        read_shapes("(sphere 0 0 0 1", false);
+    }
+
+    #[test]
+    #[should_panic(expected = "Sdf scene 'sphere' expects 4 numbers and 0 shapes")]
+    fn wrong_arguments() {
+        read_shapes("(sphere 0 0 1)", false);
+    }
+
+    #[test]
+    #[should_panic(expected = "Unknown sdf scene shape 'cone'")]
+    fn unknown_shape() {
+        read_shapes("(cone 0 0 0 1)", false);
+    }
+}
@@ -0,0 +1,186 @@
+use std::{
+    fs::{self, File},
+    io::BufReader,
+    path::{Path, PathBuf},
+};
+
+use image::{DynamicImage, ImageReader};
+use tiff::{
+    decoder::{Decoder, DecodingResult, Limits},
+    ColorType,
+};
+
+use crate::volume::Volume;
+
+/// Extensions of image inputs and of the slices read from a directory
+pub const IMAGE_EXTENSIONS: [&str; 6] = ["png", "tif", "tiff", "jpg", "jpeg", "bmp"];
+
+/// Loads an image stack, from a directory of slice images sorted by file
+/// name, or the pages of a tiff file. Intensities keep the range of the
+/// files, e.g. signed 16 bit for CT scans
+pub fn load_slices<P: AsRef<Path>>(path: P, pixel_size: f32, slice_spacing: f32) -> Volume {
+    let path = path.as_ref();
+
+    let files = match path.is_dir() {
+        true => {
+            let mut files = fs::read_dir(path)
+                .unwrap()
+                .map(|entry| entry.unwrap().path())
+                .filter(|file| {
+                    file.extension()
+                        .map(|e| e.to_string_lossy().to_lowercase())
+                        .is_some_and(|e| IMAGE_EXTENSIONS.contains(&e.as_str()))
+                })
+                .collect::<Vec<PathBuf>>();
+
+            files.sort_by_key(|file| natural_key(&file.file_name().unwrap().to_string_lossy()));
+            files
+        }
+        false => vec![path.to_path_buf()],
+    };
+
+    let mut size = None;
+    let mut values = Vec::new();
+    let mut slices = 0;
+
+    for file in &files {
+        let tiff = file
+            .extension()
+            .is_some_and(|e| e.eq_ignore_ascii_case("tif") || e.eq_ignore_ascii_case("tiff"));
+
+        let pages = match tiff {
+            true => read_tiff(file),
+            false => vec![read_image(file)],
+        };
+
+        for (width, height, page) in pages {
+            assert!(
+                *size.get_or_insert((width, height)) == (width, height),
+                "Slice '{}' has a different size than the previous ones",
+                file.display()
+            );
+
+            values.extend(page);
+            slices += 1;
+        }
+    }
+
+    let (width, height) = size.expect("Image stack has no slices");
+
+    Volume::new([width, height, slices], values, pixel_size, slice_spacing)
+}
+
+/// Whether a file is a tiff with more than one page
+pub fn is_multi_page_tiff(path: &Path) -> bool {
+    let tiff = path
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("tif") || e.eq_ignore_ascii_case("tiff"));
+
+    tiff && Decoder::new(BufReader::new(File::open(path).unwrap()))
+        .expect("Invalid tiff file")
+        .more_images()
+}
+
+/// Intensity of every pixel of every page, colours are averaged
+fn read_tiff(path: &Path) -> Vec<(u32, u32, Vec<f32>)> {
+    let file = BufReader::new(File::open(path).unwrap());
+    let mut decoder = Decoder::new(file)
+        .expect("Invalid tiff file")
+        .with_limits(Limits::unlimited());
+
+    let mut pages = Vec::new();
+
+    loop {
+        let (width, height) = decoder.dimensions().unwrap();
+
+        // Samples per pixel and how many of them are averaged
+        let (samples, used) = match decoder.colortype().unwrap() {
+            ColorType::Gray(_) => (1, 1),
+            ColorType::GrayA(_) => (2, 1),
+            ColorType::RGB(_) => (3, 3),
+            ColorType::RGBA(_) => (4, 3),
+            other => panic!("Unsupported tiff colour type {:?}", other),
+        };
+
+        let data: Vec<f32> = match decoder.read_image().unwrap() {
+            DecodingResult::U8(v) => v.into_iter().map(|s| s as f32).collect(),
+            DecodingResult::U16(v) => v.into_iter().map(|s| s as f32).collect(),
+            DecodingResult::U32(v) => v.into_iter().map(|s| s as f32).collect(),
+            DecodingResult::U64(v) => v.into_iter().map(|s| s as f32).collect(),
+            DecodingResult::I8(v) => v.into_iter().map(|s| s as f32).collect(),
+            DecodingResult::I16(v) => v.into_iter().map(|s| s as f32).collect(),
+            DecodingResult::I32(v) => v.into_iter().map(|s| s as f32).collect(),
+            DecodingResult::I64(v) => v.into_iter().map(|s| s as f32).collect(),
+            DecodingResult::F32(v) => v,
+            DecodingResult::F64(v) => v.into_iter().map(|s| s as f32).collect(),
+        };
+
+        let page = data
+            .chunks_exact(samples)
+            .map(|pixel| pixel[..used].iter().sum::<f32>() / used as f32)
+            .collect();
+
+        pages.push((width, height, page));
+
+        if !decoder.more_images() {
+            break;
+        }
+
+        decoder.next_image().unwrap();
+    }
+
+    pages
+}
+
+/// Intensity of every pixel of an image, 8 or 16 bit
+fn read_image(path: &Path) -> (u32, u32, Vec<f32>) {
+    let image = ImageReader::open(path)
+        .unwrap()
+        .with_guessed_format()
+        .unwrap()
+        .decode()
+        .unwrap();
+
+    let (width, height) = (image.width(), image.height());
+
+    let values = match image {
+        DynamicImage::ImageLuma16(_)
+        | DynamicImage::ImageLumaA16(_)
+        | DynamicImage::ImageRgb16(_)
+        | DynamicImage::ImageRgba16(_) => image
+            .to_luma16()
+            .into_raw()
+            .into_iter()
+            .map(|v| v as f32)
+            .collect(),
+        _ => image
+            .to_luma8()
+            .into_raw()
+            .into_iter()
+            .map(|v| v as f32)
+            .collect(),
+    };
+
+    (width, height, values)
+}
+
+/// Sort key ordering numbers by value, `slice_2` before `slice_10`
+fn natural_key(name: &str) -> String {
+    let mut key = String::new();
+    let mut digits = String::new();
+
+    for c in name.chars().chain(std::iter::once('\0')) {
+        match c.is_ascii_digit() {
+            true => digits.push(c),
+            false => {
+                if !digits.is_empty() {
+                    key.push_str(&format!("{:0>20}", digits));
+                    digits.clear();
+                }
+                key.push(c);
+            }
+        }
+    }
+
+    key
+}
@@ -0,0 +1,120 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, Read},
+    path::Path,
+};
+
+use nalgebra::{Matrix3, Quaternion, UnitQuaternion, Vector3};
+
+use crate::{
+    formats::ply::read_header,
+    pointcloud::LocalOrigin,
+    splat::{Splat, Splats},
+};
+
+/// Zeroth order spherical harmonic, scaling the DC colour coefficients
+const SH_C0: f32 = 0.282_094_8;
+
+const PROPERTIES: [&str; 14] = [
+    "x", "y", "z", "f_dc_0", "f_dc_1", "f_dc_2", "opacity", "scale_0", "scale_1", "scale_2",
+    "rot_0", "rot_1", "rot_2", "rot_3",
+];
+
+/// Whether a ply file holds 3D Gaussian splats
+pub fn is_splat_ply<P: AsRef<Path>>(path: P) -> bool {
+    let reader = BufReader::new(File::open(path.as_ref()).unwrap());
+
+    // Vertex property names, read leniently since any ply can be checked
+    let mut names = Vec::new();
+    let mut vertex = false;
+
+    for line in reader.split(b'\n') {
+        let line = match line {
+            Ok(line) => String::from_utf8_lossy(&line).to_string(),
+            Err(_) => return false,
+        };
+
+        match line.split_whitespace().collect::<Vec<&str>>().as_slice() {
+            ["end_header"] => break,
+            ["element", element, ..] => vertex = *element == "vertex",
+            ["property", _, name] if vertex => names.push(name.to_string()),
+            _ => {}
+        }
+    }
+
+    PROPERTIES
+        .iter()
+        .all(|name| names.iter().any(|n| n == name))
+}
+
+/// Loads the Gaussian splats of a 3D Gaussian Splatting ply file, the ones
+/// with an opacity below `min_opacity` are dropped. Returns the splats and
+/// the number of dropped ones
+pub fn load_splats<P: AsRef<Path>>(
+    path: P,
+    min_opacity: f32,
+    origin: &LocalOrigin,
+) -> (Splats, usize) {
+    let mut reader = BufReader::new(File::open(path.as_ref()).unwrap());
+
+    let layout = read_header(&mut reader);
+
+    let properties = PROPERTIES.map(|name| {
+        layout
+            .property(name)
+            .unwrap_or_else(|| panic!("Splat ply vertices have no {}", name))
+    });
+
+    let mut splats = Vec::with_capacity(layout.count);
+    let mut record = vec![0u8; layout.stride];
+
+    for _ in 0..layout.count {
+        reader.read_exact(&mut record).unwrap();
+
+        let [x, y, z, dc_0, dc_1, dc_2, opacity, scale_0, scale_1, scale_2, rot_0, rot_1, rot_2, rot_3] =
+            properties.map(|property| layout.read(&record, property));
+
+        // Opacities are stored as logits
+        let opacity = 1.0 / (1.0 + (-opacity as f32).exp());
+
+        if opacity < min_opacity || x.is_nan() || y.is_nan() || z.is_nan() {
+            continue;
+        }
+
+        let color = [dc_0, dc_1, dc_2]
+            .map(|dc| ((0.5 + SH_C0 * dc as f32) * 255.0).clamp(0.0, 255.0) as u8);
+
+        // Scales are stored as logarithms, rotations as w, x, y, z
+        let scale = Vector3::new(scale_0, scale_1, scale_2).map(|s| (s as f32).exp());
+        let rotation = UnitQuaternion::from_quaternion(Quaternion::new(
+            rot_0 as f32,
+            rot_1 as f32,
+            rot_2 as f32,
+            rot_3 as f32,
+        ));
+
+        splats.push((
+            Vector3::new(x, y, z),
+            Splat {
+                center: Vector3::zeros(),
+                axes: rotation.to_rotation_matrix().matrix() * Matrix3::from_diagonal(&scale),
+                opacity,
+                color,
+            },
+        ));
+    }
+
+    let filtered = layout.count - splats.len();
+
+    let offset = origin.resolve(splats.iter().map(|(p, _)| p));
+
+    let splats = splats
+        .into_iter()
+        .map(|(position, splat)| Splat {
+            center: (position - offset).cast::<f32>(),
+            ..splat
+        })
+        .collect();
+
+    (Splats::new(splats, offset), filtered)
+}
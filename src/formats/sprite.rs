@@ -0,0 +1,33 @@
+use std::path::Path;
+
+use image::{ImageReader, RgbaImage};
+
+use crate::sprite::Sprite;
+
+/// Loads a front sprite, with optional back and side sprites combined by
+/// intersection
+pub fn load_sprite<P: AsRef<Path>>(
+    front: P,
+    back: Option<&Path>,
+    side: Option<&Path>,
+    depth: Option<u32>,
+    pixel_size: f32,
+) -> Sprite {
+    Sprite::new(
+        open(front.as_ref()),
+        back.map(open),
+        side.map(open),
+        depth,
+        pixel_size,
+    )
+}
+
+fn open(path: &Path) -> RgbaImage {
+    ImageReader::open(path)
+        .unwrap()
+        .with_guessed_format()
+        .unwrap()
+        .decode()
+        .unwrap()
+        .to_rgba8()
+}
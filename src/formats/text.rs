@@ -0,0 +1,205 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader},
+    path::Path,
+};
+
+use nalgebra::Vector3;
+
+use crate::pointcloud::{LocalOrigin, PointCloud};
+
+/// Meaning of a column of a text point cloud
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Column {
+    X,
+    Y,
+    Z,
+    Red,
+    Green,
+    Blue,
+    Intensity,
+    Skip,
+}
+
+impl Column {
+    /// Parses a column name, unknown names are skipped
+    pub fn parse(name: &str) -> Self {
+        match name.trim().trim_matches('"').to_lowercase().as_str() {
+            "x" | "//x" => Column::X,
+            "y" => Column::Y,
+            "z" => Column::Z,
+            "r" | "red" => Column::Red,
+            "g" | "green" => Column::Green,
+            "b" | "blue" => Column::Blue,
+            "i" | "intensity" => Column::Intensity,
+            _ => Column::Skip,
+        }
+    }
+}
+
+/// Text point cloud layouts
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextFormat {
+    /// `x y z [r g b]` or `x y z intensity` per line
+    Xyz,
+    /// Leica pts, a point count line followed by `x y z intensity [r g b]`
+    Pts,
+    /// Delimited values, columns are named by the header line
+    Csv,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct TextOptions {
+    /// Column mapping, overriding the header and the format defaults
+    pub columns: Option<Vec<Column>>,
+    /// Value delimiter, whitespace, commas, semicolons and tabs are
+    /// accepted when unset
+    pub delimiter: Option<char>,
+}
+
+/// Loads a text point cloud. Colours stored between 0 and 1 are scaled to 8
+/// bit, intensities are normalized over the file when there is no colour
+pub fn load_text<P: AsRef<Path>>(
+    path: P,
+    format: TextFormat,
+    options: &TextOptions,
+    origin: &LocalOrigin,
+) -> PointCloud {
+    let reader = BufReader::new(File::open(path.as_ref()).unwrap());
+
+    let split = |line: &str| -> Vec<String> {
+        match options.delimiter {
+            Some(delimiter) => line
+                .split(delimiter)
+                .map(|v| v.trim().to_string())
+                .collect(),
+            None => line
+                .split(|c: char| c.is_whitespace() || c == ',' || c == ';')
+                .filter(|v| !v.is_empty())
+                .map(str::to_string)
+                .collect(),
+        }
+    };
+
+    let mut columns = options.columns.clone();
+
+    let mut positions = Vec::new();
+    let mut colors = Vec::new();
+    let mut intensities = Vec::new();
+
+    for (i, line) in reader.lines().enumerate() {
+        let line = line.unwrap();
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let values = split(line);
+
+        // Point count of pts files
+        if format == TextFormat::Pts && values.len() == 1 {
+            continue;
+        }
+
+        let numbers = values
+            .iter()
+            .map(|v| v.parse::<f64>())
+            .collect::<Result<Vec<f64>, _>>();
+
+        let numbers = match numbers {
+            Ok(numbers) => numbers,
+            // Header line naming the columns, or a comment
+            Err(_) if positions.is_empty() => {
+                let header = values.iter().map(|v| Column::parse(v)).collect::<Vec<_>>();
+
+                let named = [Column::X, Column::Y, Column::Z]
+                    .iter()
+                    .all(|c| header.contains(c));
+
+                if named && columns.is_none() {
+                    columns = Some(header);
+                }
+                continue;
+            }
+            Err(_) => panic!("Invalid point cloud line {}: '{}'", i + 1, line),
+        };
+
+        let columns = columns.get_or_insert_with(|| match format {
+            TextFormat::Pts => vec![
+                Column::X,
+                Column::Y,
+                Column::Z,
+                Column::Intensity,
+                Column::Red,
+                Column::Green,
+                Column::Blue,
+            ],
+            TextFormat::Xyz | TextFormat::Csv => match numbers.len() {
+                4 => vec![Column::X, Column::Y, Column::Z, Column::Intensity],
+                _ => vec![
+                    Column::X,
+                    Column::Y,
+                    Column::Z,
+                    Column::Red,
+                    Column::Green,
+                    Column::Blue,
+                ],
+            },
+        });
+
+        let mut position = Vector3::repeat(f64::NAN);
+        let mut color = None;
+        let mut intensity = None;
+
+        for (column, value) in columns.iter().zip(numbers) {
+            match column {
+                Column::X => position.x = value,
+                Column::Y => position.y = value,
+                Column::Z => position.z = value,
+                Column::Red => color.get_or_insert([0.0; 3])[0] = value,
+                Column::Green => color.get_or_insert([0.0; 3])[1] = value,
+                Column::Blue => color.get_or_insert([0.0; 3])[2] = value,
+                Column::Intensity => intensity = Some(value),
+                Column::Skip => {}
+            }
+        }
+
+        if position.iter().any(|v| v.is_nan()) {
+            continue;
+        }
+
+        positions.push(position);
+        colors.push(color);
+        intensities.push(intensity);
+    }
+
+    let color_scale = match colors.iter().flatten().flatten().any(|c| *c > 1.0) {
+        true => 1.0,
+        false => 255.0,
+    };
+
+    let (min, max) = intensities
+        .iter()
+        .flatten()
+        .fold((f64::MAX, f64::MIN), |(min, max), i| {
+            (min.min(*i), max.max(*i))
+        });
+    let range = (max - min).max(f64::EPSILON);
+
+    let points = positions
+        .into_iter()
+        .zip(colors.into_iter().zip(intensities))
+        .map(|(position, (color, intensity))| {
+            let color = match (color, intensity) {
+                (Some(color), _) => color.map(|c| (c * color_scale).clamp(0.0, 255.0) as u8),
+                (None, Some(intensity)) => [((intensity - min) / range * 255.0) as u8; 3],
+                (None, None) => [255; 3],
+            };
+
+            (position, [color[0], color[1], color[2], 255])
+        })
+        .collect();
+
+    PointCloud::from_global(points, origin)
+}
@@ -1,13 +1,21 @@
 use std::{
-    fs::File,
+    fs::{self, File},
     io::{BufWriter, Write},
     path::Path,
 };
 
+use ahash::AHashMap;
+use clap::ValueEnum;
 use nalgebra::Vector3;
+use serde_json::{json, Value};
+
+use crate::lattice::Lattice;
 
 const MAGIC_NUMBER: &str = "VOXELSRS";
 
+/// Voxel position and colour
+pub type Voxel = (Vector3<i32>, [u8; 4]);
+
 pub fn save_voxels<P: AsRef<Path>>(path: P, voxels: &[(Vector3<i32>, [u8; 4])]) {
     let mut writer = BufWriter::new(File::create(path).unwrap());
 
@@ -35,3 +43,187 @@ pub fn save_voxels<P: AsRef<Path>>(path: P, voxels: &[(Vector3<i32>, [u8; 4])])
             .unwrap();
     }
 }
+
+/// Grid description, stored in the header of ply outputs and next to voxel
+/// format outputs
+#[derive(Clone, Debug)]
+pub struct Metadata {
+    /// Voxel size along each axis
+    pub voxel_size: Vector3<f32>,
+    /// World position of the lattice point of voxel (0, 0, 0)
+    pub origin: Vector3<f32>,
+    /// Global position of the local frame the voxelization ran in, the
+    /// global position of a voxel is `offset + origin + index * voxel_size`
+    pub offset: Vector3<f64>,
+}
+
+impl Metadata {
+    pub fn new(lattice: &Lattice, offset: Vector3<f64>) -> Self {
+        Self {
+            voxel_size: lattice.resolution,
+            origin: lattice.origin,
+            offset,
+        }
+    }
+
+    /// Whether voxel indices are world positions already, unit voxels from
+    /// the world origin
+    pub fn is_identity(&self) -> bool {
+        self.voxel_size == Vector3::repeat(1.0)
+            && self.origin == Vector3::zeros()
+            && self.offset == Vector3::zeros()
+    }
+
+    /// Saves the metadata as a json object, for the formats that can't store
+    /// it themselves
+    pub fn save<P: AsRef<Path>>(&self, path: P) {
+        // Through the shortest decimal form, so 0.1 stays 0.1 in double
+        // precision
+        let values = |v: &Vector3<f32>| {
+            v.iter()
+                .map(|c| c.to_string().parse::<f64>().unwrap())
+                .collect::<Vec<f64>>()
+        };
+
+        let json = json!({
+            "voxel_size": values(&self.voxel_size),
+            "origin": values(&self.origin),
+            "global_offset": self.offset.as_slice(),
+        });
+
+        let mut writer = BufWriter::new(File::create(path).unwrap());
+        serde_json::to_writer_pretty(&mut writer, &json).unwrap();
+        writeln!(writer).unwrap();
+    }
+
+    /// Whether a file holds metadata saved earlier, which can be replaced
+    pub fn is_saved<P: AsRef<Path>>(path: P) -> bool {
+        fs::read_to_string(path)
+            .ok()
+            .and_then(|source| serde_json::from_str::<Value>(&source).ok())
+            .is_some_and(|json| {
+                json.get("voxel_size").is_some() && json.get("global_offset").is_some()
+            })
+    }
+}
+
+/// Optional per-voxel channels, aligned with the voxel list they belong to
+#[derive(Clone, Debug, Default)]
+pub struct Channels {
+    /// Material index of every voxel, `NO_MATERIAL` when it has none
+    pub materials: Option<Vec<u32>>,
+    /// Names of the material indices
+    pub material_names: Vec<(u32, String)>,
+    /// Number of points or ray hits that fell in every voxel
+    pub counts: Option<Vec<u32>>,
+    /// Occupancy probability of every voxel, or its count divided by the
+    /// highest count
+    pub occupancy: Option<Vec<f32>>,
+    /// `FREE` or `OCCUPIED` state of every voxel of an occupancy grid
+    pub states: Option<Vec<u8>>,
+}
+
+impl Channels {
+    /// Stores sample counts as the channel picked by `mode`
+    pub fn set_counts(&mut self, counts: Vec<u32>, mode: Counts) {
+        match mode {
+            Counts::Count => self.counts = Some(counts),
+            Counts::Occupancy => {
+                let max = counts.iter().copied().max().unwrap_or(1).max(1) as f32;
+
+                self.occupancy = Some(counts.iter().map(|c| *c as f32 / max).collect());
+            }
+        }
+    }
+}
+
+/// How per-voxel sample counts are stored
+#[derive(ValueEnum, Clone, Copy, Debug, Default)]
+pub enum Counts {
+    /// Number of points or ray hits in the voxel
+    #[default]
+    Count,
+    /// Count normalized by the highest count, between 0 and 1
+    Occupancy,
+}
+
+pub const NO_MATERIAL: u32 = u32::MAX;
+
+/// Voxel states of an occupancy grid, voxels missing from it are unknown
+pub const FREE: u8 = 1;
+pub const OCCUPIED: u8 = 2;
+
+/// Rule picking the material of a voxel hit by several materials
+#[derive(ValueEnum, Clone, Copy, Debug, Default)]
+pub enum MaterialPriority {
+    /// Material with the most hits in the voxel, ties go to the lowest index
+    #[default]
+    Majority,
+    /// Lowest material index
+    Lowest,
+    /// Highest material index
+    Highest,
+}
+
+type MaterialHits = (u32, u32, [u8; 4]);
+
+/// Deduplicates voxels, keeping the colour of one of the hits, along with
+/// the number of hits of every voxel
+pub fn deduplicate(voxels: Vec<Voxel>) -> (Vec<Voxel>, Vec<u32>) {
+    let mut hits: AHashMap<Vector3<i32>, ([u8; 4], u32)> = AHashMap::new();
+
+    for (position, color) in voxels {
+        let entry = hits.entry(position).or_insert((color, 0));
+        entry.0 = color;
+        entry.1 += 1;
+    }
+
+    hits.into_iter()
+        .map(|(position, (color, count))| ((position, color), count))
+        .unzip()
+}
+
+/// Deduplicates voxels carrying a material, keeping the material picked by
+/// `priority` and the colour of one of its hits. Returns the voxels, their
+/// material and their total number of hits
+pub fn deduplicate_materials(
+    voxels: Vec<(Vector3<i32>, [u8; 4], u32)>,
+    priority: MaterialPriority,
+) -> (Vec<Voxel>, Vec<u32>, Vec<u32>) {
+    // Material, hit count and colour of every material hitting a voxel
+    let mut hits: AHashMap<Vector3<i32>, Vec<MaterialHits>> = AHashMap::new();
+
+    for (position, color, material) in voxels {
+        let hits = hits.entry(position).or_default();
+
+        match hits.iter_mut().find(|(m, _, _)| *m == material) {
+            Some((_, count, c)) => {
+                *count += 1;
+                *c = color;
+            }
+            None => hits.push((material, 1, color)),
+        }
+    }
+
+    let mut deduplicated = Vec::with_capacity(hits.len());
+    let mut materials = Vec::with_capacity(hits.len());
+    let mut counts = Vec::with_capacity(hits.len());
+
+    for (position, hits) in hits {
+        counts.push(hits.iter().map(|(_, count, _)| count).sum());
+
+        let (material, _, color) = match priority {
+            MaterialPriority::Majority => hits
+                .into_iter()
+                .max_by(|a, b| a.1.cmp(&b.1).then(b.0.cmp(&a.0))),
+            MaterialPriority::Lowest => hits.into_iter().min_by_key(|(m, _, _)| *m),
+            MaterialPriority::Highest => hits.into_iter().max_by_key(|(m, _, _)| *m),
+        }
+        .unwrap();
+
+        deduplicated.push((position, color));
+        materials.push(material);
+    }
+
+    (deduplicated, materials, counts)
+}
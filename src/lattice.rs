@@ -0,0 +1,288 @@
+use clap::ValueEnum;
+use indicatif::ProgressBar;
+use nalgebra::{Matrix4, Point3, Vector3};
+use rayon::prelude::*;
+
+use crate::{bbox::Bbox, formats::voxels::Voxel};
+
+/// Where the lattice points sit relative to the voxels
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Convention {
+    /// Lattice points are voxel centres, voxel `i` spans `[i - 0.5, i + 0.5)`
+    #[default]
+    Center,
+    /// Lattice points are voxel corners, voxel `i` spans `[i, i + 1)`
+    Corner,
+}
+
+/// Which voxels inside a volume are kept
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Fill {
+    /// Every voxel inside
+    #[default]
+    Solid,
+    /// Voxels inside next to one outside
+    Surface,
+}
+
+/// Geometry voxelized by sampling the lattice at voxel centres, in its own
+/// source space
+pub trait Sampled {
+    /// Bounding box in source space
+    fn bbox(&self) -> Bbox;
+
+    /// Voxels covered by the geometry, `transform` taking the source to the
+    /// world
+    fn voxelize(
+        &self,
+        lattice: &Lattice,
+        transform: &Matrix4<f32>,
+        bar: &ProgressBar,
+    ) -> Vec<Voxel>;
+}
+
+/// Regular grid the geometry is voxelized on
+#[derive(Clone, Copy, Debug)]
+pub struct Lattice {
+    /// Voxel size along each axis
+    pub resolution: Vector3<f32>,
+    /// World position of the lattice point of voxel (0, 0, 0)
+    pub origin: Vector3<f32>,
+    pub convention: Convention,
+}
+
+impl Lattice {
+    pub fn new(resolution: Vector3<f32>, origin: Vector3<f32>, convention: Convention) -> Self {
+        Self {
+            resolution,
+            origin,
+            convention,
+        }
+    }
+
+    /// Voxel containing a world position, cells are half-open on the
+    /// positive side so positions on a boundary go to the upper voxel on
+    /// both sides of the origin
+    pub fn voxel(&self, pos: &Vector3<f32>) -> Vector3<i32> {
+        self.cell(pos).map(|v| v.floor() as i32)
+    }
+
+    /// Position in voxel units shifted so that voxel `i` spans `[i, i + 1)`
+    pub fn cell(&self, pos: &Vector3<f32>) -> Vector3<f32> {
+        let shift = match self.convention {
+            Convention::Center => 0.5,
+            Convention::Corner => 0.0,
+        };
+
+        self.grid(pos).add_scalar(shift)
+    }
+
+    /// Calls `f` with every voxel crossed by the segment from `from` to `to`,
+    /// in order, excluding the voxel containing `to`
+    pub fn traverse(
+        &self,
+        from: &Vector3<f32>,
+        to: &Vector3<f32>,
+        mut f: impl FnMut(Vector3<i32>),
+    ) {
+        let start = self.cell(from);
+        let direction = self.cell(to) - start;
+
+        let mut voxel = self.voxel(from);
+        let end = self.voxel(to);
+
+        let step = direction.map(|d| d.signum() as i32);
+
+        // Segment parameter at the next boundary crossing along each axis
+        // and between two crossings, the segment spans [0, 1]
+        let mut next = Vector3::zeros();
+        let mut delta = Vector3::zeros();
+
+        for axis in 0..3 {
+            match direction[axis] == 0.0 {
+                true => {
+                    next[axis] = f32::INFINITY;
+                    delta[axis] = f32::INFINITY;
+                }
+                false => {
+                    let boundary = match step[axis] > 0 {
+                        true => voxel[axis] as f32 + 1.0,
+                        false => voxel[axis] as f32,
+                    };
+
+                    next[axis] = (boundary - start[axis]) / direction[axis];
+                    delta[axis] = 1.0 / direction[axis].abs();
+                }
+            }
+        }
+
+        // Stepping exactly the voxel distance to the end keeps rounding
+        // errors from overshooting it
+        let steps = (end - voxel).abs().sum();
+
+        for _ in 0..steps {
+            f(voxel);
+
+            let axis = next.imin();
+
+            voxel[axis] += step[axis];
+            next[axis] += delta[axis];
+        }
+    }
+
+    /// World position of the centre of a voxel
+    pub fn center(&self, voxel: &Vector3<i32>) -> Vector3<f32> {
+        let shift = match self.convention {
+            Convention::Center => 0.0,
+            Convention::Corner => 0.5,
+        };
+
+        self.origin
+            + voxel
+                .map(|v| v as f32 + shift)
+                .component_mul(&self.resolution)
+    }
+
+    /// Calls `f` in parallel with every voxel in `bbox` and its centre in
+    /// source space, `transform` taking the source to the world, keeping the
+    /// voxels it colours
+    pub fn sample(
+        &self,
+        bbox: &Bbox,
+        transform: &Matrix4<f32>,
+        f: impl Fn(&Vector3<i32>, &Vector3<f32>) -> Option<[u8; 4]> + Sync,
+        bar: &ProgressBar,
+    ) -> Vec<Voxel> {
+        let inverse = transform
+            .try_inverse()
+            .expect("Source transform isn't invertible");
+
+        let min = self.voxel(&bbox.min);
+        let max = self.voxel(&bbox.max);
+
+        bar.set_length((max.x - min.x + 1) as u64);
+
+        (min.x..=max.x)
+            .into_par_iter()
+            .flat_map_iter(|x| {
+                let mut voxels = Vec::new();
+
+                for y in min.y..=max.y {
+                    for z in min.z..=max.z {
+                        let voxel = Vector3::new(x, y, z);
+                        let position = inverse
+                            .transform_point(&Point3::from(self.center(&voxel)))
+                            .coords;
+
+                        if let Some(color) = f(&voxel, &position) {
+                            voxels.push((voxel, color));
+                        }
+                    }
+                }
+
+                bar.inc(1);
+                voxels
+            })
+            .collect()
+    }
+
+    /// Whether one of the 6 neighbours of a voxel is outside, `inside`
+    /// taking source space positions and `inverse` the world to the source
+    pub fn exposed(
+        &self,
+        voxel: &Vector3<i32>,
+        inverse: &Matrix4<f32>,
+        inside: impl Fn(&Vector3<f32>) -> bool,
+    ) -> bool {
+        (0..6).any(|i| {
+            let mut neighbor = *voxel;
+            neighbor[i / 2] += if i % 2 == 0 { 1 } else { -1 };
+
+            !inside(
+                &inverse
+                    .transform_point(&Point3::from(self.center(&neighbor)))
+                    .coords,
+            )
+        })
+    }
+
+    /// Position in voxel units of a world position
+    pub fn grid(&self, pos: &Vector3<f32>) -> Vector3<f32> {
+        (pos - self.origin).component_div(&self.resolution)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lattice(convention: Convention) -> Lattice {
+        Lattice::new(Vector3::repeat(1.0), Vector3::zeros(), convention)
+    }
+
+    fn voxel(lattice: &Lattice, x: f32, y: f32, z: f32) -> [i32; 3] {
+        lattice.voxel(&Vector3::new(x, y, z)).into()
+    }
+
+    #[test]
+    fn center_voxels() {
+        let lattice = lattice(Convention::Center);
+
+        assert_eq!(voxel(&lattice, 0.0, 0.4, -0.4), [0, 0, 0]);
+        assert_eq!(voxel(&lattice, 0.6, -0.6, 1.4), [1, -1, 1]);
+        assert_eq!(voxel(&lattice, -1.4, -1.6, -2.7), [-1, -2, -3]);
+    }
+
+    #[test]
+    fn center_boundaries() {
+        let lattice = lattice(Convention::Center);
+
+        assert_eq!(voxel(&lattice, 0.5, -0.5, 1.5), [1, 0, 2]);
+        assert_eq!(voxel(&lattice, -1.5, -2.5, 2.5), [-1, -2, 3]);
+    }
+
+    #[test]
+    fn corner_voxels() {
+        let lattice = lattice(Convention::Corner);
+
+        assert_eq!(voxel(&lattice, 0.1, 0.9, -0.1), [0, 0, -1]);
+        assert_eq!(voxel(&lattice, -0.9, -1.1, 1.6), [-1, -2, 1]);
+    }
+
+    #[test]
+    fn corner_boundaries() {
+        let lattice = lattice(Convention::Corner);
+
+        assert_eq!(voxel(&lattice, 0.0, 1.0, -1.0), [0, 1, -1]);
+        assert_eq!(voxel(&lattice, -2.0, 2.0, -3.0), [-2, 2, -3]);
+    }
+
+    #[test]
+    fn anisotropic_voxels() {
+        let lattice = Lattice::new(
+            Vector3::new(0.5, 1.0, 2.0),
+            Vector3::new(1.0, 0.0, -1.0),
+            Convention::Corner,
+        );
+
+        assert_eq!(voxel(&lattice, 1.0, 0.0, -1.0), [0, 0, 0]);
+        assert_eq!(voxel(&lattice, 0.9, -0.1, -3.0), [-1, -1, -1]);
+        assert_eq!(voxel(&lattice, 1.5, 2.5, 3.5), [1, 2, 2]);
+    }
+
+    #[test]
+    fn centers_are_inside_their_voxel() {
+        for convention in [Convention::Center, Convention::Corner] {
+            let lattice = Lattice::new(
+                Vector3::new(0.5, 1.0, 2.0),
+                Vector3::new(0.3, -0.2, 0.1),
+                convention,
+            );
+
+            for v in [[0, 0, 0], [-1, 2, -3], [4, -5, 6]] {
+                let v = Vector3::from(v);
+                assert_eq!(lattice.voxel(&lattice.center(&v)), v);
+            }
+        }
+    }
+}
@@ -1,17 +1,55 @@
-use ahash::HashMap;
-use clap::Parser;
-use formats::{gltf::load_gltf, ply::load_ply, voxels::save_voxels};
+use bbox::Bbox;
+use clap::{ArgGroup, Parser, ValueEnum};
+use formats::{
+    colmap::{fused_track_lengths, is_colmap_points, load_colmap_points, ColmapOptions},
+    e57::load_e57,
+    gltf::{load_gltf, GltfFile, GltfOptions, NodeFilter, Pose, SceneSelection},
+    heightmap::load_heightmap,
+    las::{load_las, LasColor, LasOptions},
+    pcd::load_pcd,
+    ply::{load_ply, save_voxels_ply},
+    rgbd::load_rgbd,
+    sdf::load_sdf,
+    slices::{is_multi_page_tiff, load_slices, IMAGE_EXTENSIONS},
+    splat::{is_splat_ply, load_splats},
+    sprite::load_sprite,
+    text::{load_text, Column, TextFormat, TextOptions},
+    voxels::{
+        deduplicate, deduplicate_materials, save_voxels, Channels, Counts, MaterialPriority,
+        Metadata, Voxel, NO_MATERIAL,
+    },
+};
 use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
-use nalgebra::Vector3;
-use std::{path::PathBuf, str::FromStr, time::Instant};
+use lattice::{Convention, Fill, Lattice, Sampled};
+use mesh::Mesh;
+use nalgebra::{Matrix3, Matrix4, Quaternion, Unit, UnitQuaternion, Vector3};
+use pointcloud::{Carving, LocalOrigin, PointCloud, PointFilter};
+use splat::SplatMode;
+use std::{
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Instant,
+};
+use transform::{conversion, Frame, Transform, Units};
+use tsdf::{depth_bbox, Tsdf};
+use volume::Transfer;
 
 pub mod bbox;
 pub mod formats;
+pub mod lattice;
 pub mod mesh;
 pub mod pointcloud;
+pub mod sdf;
+pub mod splat;
+pub mod sprite;
+pub mod terrain;
+pub mod transform;
+pub mod tsdf;
+pub mod volume;
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
+#[command(group(ArgGroup::new("size").required(true).args(["resolution", "fit", "fit_box"])))]
 struct Args {
     /// Path to mesh
     #[arg(short, long)]
@@ -21,9 +59,22 @@ struct Args {
     #[arg(short, long)]
     output: String,
 
-    /// Resolution
-    #[arg(short, long)]
-    resolution: f32,
+    /// Resolution, or comma separated x,y,z voxel sizes
+    #[arg(short, long, value_delimiter = ',', value_parser = positive)]
+    resolution: Option<Vec<f32>>,
+
+    /// Picks the resolution so the model covers this many voxels along
+    /// `--fit-axis`
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    fit: Option<u32>,
+
+    /// Axis measured by `--fit`
+    #[arg(long, value_enum, default_value_t = FitAxis::Longest)]
+    fit_axis: FitAxis,
+
+    /// Picks the resolution so the model fits in a box of x,y,z voxels
+    #[arg(long, value_delimiter = ',', value_parser = clap::value_parser!(u32).range(1..))]
+    fit_box: Option<Vec<u32>>,
 
     /// x-axis rotation
     #[arg(short, long)]
@@ -36,6 +87,433 @@ struct Args {
     /// z-axis rotation
     #[arg(short, long)]
     z_rotation: Option<f32>,
+
+    /// Uniform scale, or comma separated x,y,z scale
+    #[arg(long, value_delimiter = ',', allow_hyphen_values = true)]
+    scale: Option<Vec<f32>>,
+
+    /// Comma separated x,y,z translation
+    #[arg(long, value_delimiter = ',', allow_hyphen_values = true)]
+    translate: Option<Vec<f32>>,
+
+    /// Comma separated x,y,z,w rotation quaternion
+    #[arg(long, value_delimiter = ',', allow_hyphen_values = true)]
+    quaternion: Option<Vec<f32>>,
+
+    /// Comma separated x,y,z rotation axis and angle in degrees
+    #[arg(long, value_delimiter = ',', allow_hyphen_values = true)]
+    axis_angle: Option<Vec<f32>>,
+
+    /// Comma separated 4x4 matrix in row-major order, applied before the
+    /// scale, rotations (Euler, quaternion then axis-angle) and translation
+    #[arg(long, value_delimiter = ',', allow_hyphen_values = true)]
+    matrix: Option<Vec<f32>>,
+
+    /// Coordinate frame of the input, defaults to y-up for glTF and z-up for
+    /// point clouds
+    #[arg(long, value_enum)]
+    input_frame: Option<Frame>,
+
+    /// Coordinate frame of the voxels
+    #[arg(long, value_enum, default_value_t = Frame::YUp)]
+    output_frame: Frame,
+
+    /// Length units of the input, converted to meters
+    #[arg(long, value_enum, default_value_t = Units::M)]
+    units: Units,
+
+    /// Where voxel (0, 0, 0) is placed relative to the model
+    #[arg(long, value_enum, default_value_t = Anchor::World)]
+    anchor: Anchor,
+
+    /// Origin subtracted from point cloud coordinates, read in double
+    /// precision, before voxelizing: `auto` for the bounding box centre,
+    /// rounded in the output frame to a multiple of `--resolution` or to
+    /// whole units when fitting, or comma separated x,y,z. Stored as the global offset of the
+    /// output. Meshes are read in single precision and ignore it
+    #[arg(long, allow_hyphen_values = true)]
+    local_origin: Option<String>,
+
+    /// Whether lattice points are voxel centres or voxel corners
+    #[arg(long, value_enum, default_value_t = Convention::Center)]
+    lattice: Convention,
+
+    /// Comma separated x,y,z offset of the grid origin, added to the anchor,
+    /// so several inputs can share one lattice
+    #[arg(long, value_delimiter = ',', allow_hyphen_values = true)]
+    grid_offset: Option<Vec<f32>>,
+
+    /// Drop point cloud voxels containing fewer points
+    #[arg(long, default_value_t = 1)]
+    min_points: usize,
+
+    /// Remove statistical point cloud outliers, comparing the mean distance
+    /// of every point to this many nearest neighbours
+    #[arg(long)]
+    outlier_neighbors: Option<usize>,
+
+    /// Points whose mean neighbour distance is more than this many standard
+    /// deviations above the average are statistical outliers
+    #[arg(long, default_value_t = 2.0)]
+    outlier_std_ratio: f32,
+
+    /// Remove point cloud points with too few neighbours within this radius,
+    /// after transforms
+    #[arg(long)]
+    outlier_radius: Option<f32>,
+
+    /// Neighbours required within `--outlier-radius`
+    #[arg(long, default_value_t = 2)]
+    outlier_min_neighbors: usize,
+
+    /// Comma separated x,y,z position of the sensor of a point cloud, in
+    /// input coordinates. Ply files can also give a sensor_x, sensor_y and
+    /// sensor_z per point
+    #[arg(long, value_delimiter = ',', allow_hyphen_values = true)]
+    sensor_origin: Option<Vec<f64>>,
+
+    /// Carve the free space between the sensor and the points, writing both
+    /// occupied and free voxels, free ones transparent, with their state and
+    /// occupancy probability in ply outputs
+    #[arg(long, conflicts_with_all = ["counts", "min_points"])]
+    free_space: bool,
+
+    /// Occupancy probability of a voxel containing a point
+    #[arg(long, default_value_t = 0.7)]
+    prob_hit: f32,
+
+    /// Occupancy probability of a voxel crossed by a sensor ray
+    #[arg(long, default_value_t = 0.4)]
+    prob_miss: f32,
+
+    /// Cut sensor rays at this distance, after transforms
+    #[arg(long)]
+    max_range: Option<f32>,
+
+    /// Comma separated LAS classifications to keep, e.g. 2 for ground only
+    #[arg(long, value_delimiter = ',')]
+    classes: Vec<u8>,
+
+    /// Comma separated LAS classifications to drop, e.g. 7,18 for noise
+    #[arg(long, value_delimiter = ',')]
+    exclude_classes: Vec<u8>,
+
+    /// Attribute LAS points are coloured with
+    #[arg(long, value_enum, default_value_t = LasColor::Rgb)]
+    las_color: LasColor,
+
+    /// Comma separated meaning of the columns of text point clouds: x, y, z,
+    /// r, g, b, intensity, anything else is skipped. Defaults to the header
+    /// names, or x,y,z,r,g,b (x,y,z,intensity with 4 columns, and
+    /// x,y,z,intensity,r,g,b for pts)
+    #[arg(long, value_delimiter = ',')]
+    columns: Option<Vec<String>>,
+
+    /// Value delimiter of text point clouds, defaults to whitespace, commas
+    /// and semicolons
+    #[arg(long)]
+    delimiter: Option<char>,
+
+    /// Drop COLMAP points seen by fewer images, read from the `.vis` file
+    /// next to a dense `fused.ply`
+    #[arg(long, default_value_t = 1)]
+    min_track_length: usize,
+
+    /// Drop COLMAP sparse points with a larger mean reprojection error, in
+    /// pixels
+    #[arg(long)]
+    max_reprojection_error: Option<f64>,
+
+    /// How Gaussian splat ply files are voxelized
+    #[arg(long, value_enum, default_value_t = SplatMode::Ellipsoids)]
+    splat_mode: SplatMode,
+
+    /// Drop Gaussian splats less opaque than this
+    #[arg(long, default_value_t = 0.1)]
+    min_opacity: f32,
+
+    /// Gaussian splat ellipsoids are cut at this many standard deviations
+    #[arg(long, default_value_t = 2.0)]
+    splat_sigma: f32,
+
+    /// Skip Gaussian splats this many voxels wide or more along an axis.
+    /// The others are clamped to the box around the splat centres
+    #[arg(long, default_value_t = 64)]
+    max_splat_size: u32,
+
+    /// Truncation distance of the signed distance grid fused from rgbd
+    /// sequences, after transforms, defaults to 3 voxels
+    #[arg(long)]
+    truncation: Option<f32>,
+
+    /// Ignore rgbd depths beyond this many meters
+    #[arg(long)]
+    max_depth: Option<f32>,
+
+    /// What image inputs are, defaults to slices for tiff files with more
+    /// than one page and to heightmaps otherwise
+    #[arg(long, value_enum)]
+    image_input: Option<ImageInput>,
+
+    /// Which voxels of heightmap terrains and signed distance scenes are
+    /// kept
+    #[arg(long, value_enum, default_value_t = Fill::Solid)]
+    fill: Fill,
+
+    /// Horizontal size of a heightmap, image slice or sprite pixel
+    #[arg(long, default_value_t = 1.0)]
+    pixel_size: f32,
+
+    /// Height of the brightest heightmap value
+    #[arg(long, default_value_t = 255.0)]
+    height_scale: f32,
+
+    /// Colour texture stretched over the heightmap
+    #[arg(long)]
+    color_map: Option<String>,
+
+    /// Distance between image slices
+    #[arg(long, default_value_t = 1.0)]
+    slice_spacing: f32,
+
+    /// Lowest image slice intensity that is occupied, defaults to the bottom
+    /// of the window or halfway through the intensities
+    #[arg(long, allow_hyphen_values = true)]
+    threshold: Option<f32>,
+
+    /// Width of the image slice intensities spread from black to white
+    #[arg(long, requires = "level")]
+    window: Option<f32>,
+
+    /// Centre of the image slice intensity window
+    #[arg(long, requires = "window", allow_hyphen_values = true)]
+    level: Option<f32>,
+
+    /// Pixels a sprite is extruded by, defaults to the width of
+    /// `--side-sprite` or 1
+    #[arg(long)]
+    sprite_depth: Option<u32>,
+
+    /// Sprite seen from behind, intersected with the front one and
+    /// colouring the back half
+    #[arg(long)]
+    back_sprite: Option<String>,
+
+    /// Silhouette seen from the right, intersected with the extruded sprite,
+    /// its columns going from front to back
+    #[arg(long)]
+    side_sprite: Option<String>,
+
+    /// Pose of skinned glTF meshes when no animation is sampled, defaults to
+    /// the bind pose
+    #[arg(long, value_enum, conflicts_with_all = ["animation", "time", "frame_rate"])]
+    pose: Option<PoseArg>,
+
+    /// glTF animation to pose skinned meshes with, defaults to the first one
+    #[arg(long)]
+    animation: Option<String>,
+
+    /// Time in seconds at which the glTF animation is sampled, defaults to 0
+    #[arg(long)]
+    time: Option<f32>,
+
+    /// Comma separated morph target weights applied to every glTF mesh
+    #[arg(long, value_delimiter = ',')]
+    morph_weights: Option<Vec<f32>>,
+
+    /// Samples the glTF animation at this many frames per second, writing
+    /// one numbered output file per frame
+    #[arg(long, value_parser = positive)]
+    frame_rate: Option<f32>,
+
+    /// glTF scene to voxelize, by index or name, defaults to every scene
+    #[arg(long)]
+    scene: Option<String>,
+
+    /// Only voxelize glTF nodes whose name matches this glob, and their children
+    #[arg(long)]
+    include_node: Vec<String>,
+
+    /// Skip glTF nodes whose name matches this glob, and their children
+    #[arg(long)]
+    exclude_node: Vec<String>,
+
+    /// Only voxelize glTF nodes with these extras, as `key` or `key=value`
+    #[arg(long)]
+    include_extras: Vec<String>,
+
+    /// Skip glTF nodes with these extras, as `key` or `key=value`
+    #[arg(long)]
+    exclude_extras: Vec<String>,
+
+    /// Skip glTF helper geometry such as colliders, LOD1+ and proxies
+    #[arg(long)]
+    skip_helpers: bool,
+
+    /// Write one output file per glTF node or material, all on the same grid,
+    /// named after it and suffixed with its index when names are empty or
+    /// shared
+    #[arg(long, value_enum)]
+    split: Option<Split>,
+
+    /// Record the glTF material of every voxel, picking between materials
+    /// sharing a voxel with the given rule. Stored by ply outputs
+    #[arg(long, value_enum, num_args = 0..=1, default_missing_value = "majority")]
+    materials: Option<MaterialPriority>,
+
+    /// Record how many points or ray hits fell in every voxel, as a raw
+    /// count or normalized by the highest count. Stored by ply outputs
+    #[arg(long, value_enum, num_args = 0..=1, default_missing_value = "count")]
+    counts: Option<Counts>,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Anchor {
+    /// Keep world coordinates
+    World,
+    /// Bounding box minimum at the origin
+    Min,
+    /// Bounding box centre at the origin
+    Center,
+    /// Lowest point on the ground plane of the output frame
+    Ground,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum FitAxis {
+    X,
+    Y,
+    Z,
+    Longest,
+}
+
+/// How the voxel size is chosen
+enum Size {
+    Resolution(Vector3<f32>),
+    Fit(u32, FitAxis),
+    FitBox(Vector3<u32>),
+}
+
+impl Size {
+    /// Voxel size for geometry spanning `bbox`, after transforms, on a
+    /// lattice with voxel (0, 0, 0) at `origin`
+    fn resolve(&self, bbox: &Bbox, origin: &Vector3<f32>, convention: Convention) -> Vector3<f32> {
+        let resolution = match self {
+            Size::Resolution(resolution) => return *resolution,
+            Size::Fit(voxels, axis) => {
+                let axis = match axis {
+                    FitAxis::X => 0,
+                    FitAxis::Y => 1,
+                    FitAxis::Z => 2,
+                    FitAxis::Longest => bbox.size().imax(),
+                };
+
+                fit(bbox, origin, convention, axis, *voxels)
+            }
+            Size::FitBox(voxels) => (0..3)
+                .map(|axis| fit(bbox, origin, convention, axis, voxels[axis]))
+                .fold(0.0, f32::max),
+        };
+
+        assert!(
+            resolution > 0.0 && resolution.is_finite(),
+            "Invalid resolution {}, the model may be flat along the fitted axis",
+            resolution
+        );
+
+        Vector3::repeat(resolution)
+    }
+}
+
+/// Voxel size with which the model covers `voxels` voxels along an axis,
+/// its ends as far as possible from the boundaries and centres of their
+/// voxels so every voxelizer counts the same voxels. Falls back to the
+/// closest count the lattice can place, e.g. an odd count for a model
+/// centred on a voxel centre
+fn fit(
+    bbox: &Bbox,
+    origin: &Vector3<f32>,
+    convention: Convention,
+    axis: usize,
+    voxels: u32,
+) -> f32 {
+    const STEPS: u32 = 3000;
+
+    let length = bbox.max[axis] - bbox.min[axis];
+
+    // Voxels covered by the model when it is `x` voxel sizes long, with the
+    // distances of its ends to the boundaries and centres of their voxels,
+    // smallest first
+    let cover = |x: f32| {
+        let lattice = Lattice::new(Vector3::repeat(length / x), *origin, convention);
+        let min = lattice.cell(&bbox.min)[axis];
+        let max = lattice.cell(&bbox.max)[axis];
+
+        let (low, high) = (min - min.floor(), max - max.floor());
+        let mut margins = [low, 0.5 - low, high - 0.5, 1.0 - high];
+        margins.sort_by(f32::total_cmp);
+
+        ((max.floor() - min.floor()) as i64 + 1, margins)
+    };
+
+    // Lengths covering one voxel less to one more, preferring ends clear of
+    // voxel centres, then the closest count, then fewer voxels
+    let start = voxels.saturating_sub(2) as f32;
+
+    let (count, _, x) = (0..STEPS * 3)
+        .map(|i| start + (i as f32 + 0.5) / STEPS as f32)
+        .map(|x| {
+            let (count, margins) = cover(x);
+            (count, margins, x)
+        })
+        .max_by(|a, b| {
+            let key = |(count, margins, _): &(i64, [f32; 4], f32)| {
+                (
+                    margins[0] >= 0.0,
+                    -(count - voxels as i64).abs(),
+                    *count <= voxels as i64,
+                )
+            };
+
+            key(a).cmp(&key(b)).then(a.1.partial_cmp(&b.1).unwrap())
+        })
+        .unwrap();
+
+    if count != voxels as i64 {
+        eprintln!(
+            "The lattice can't place the model in exactly {} voxels along {}, it covers {}. Try another --anchor or --lattice",
+            voxels,
+            ["x", "y", "z"][axis],
+            count
+        );
+    }
+
+    length / x
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Split {
+    Node,
+    Material,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum ImageInput {
+    /// Grayscale heights, voxelized as terrain columns
+    Heightmap,
+    /// Slices of a volume, the pages of a tiff file. Directory inputs are
+    /// always slices, the images in them sorted by file name
+    Slices,
+    /// Front view of a pixel-art sprite, extruded into columns
+    Sprite,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum PoseArg {
+    /// Skinning is ignored, vertices are only placed by their node
+    Bind,
+    /// Skinning with the nodes' own transforms
+    Rest,
 }
 
 fn main() {
@@ -45,30 +523,247 @@ fn main() {
 
     let output = PathBuf::from_str(&args.output).expect("Output should be a valid path");
 
-    let rotation =
-        match args.x_rotation.is_some() || args.y_rotation.is_some() || args.z_rotation.is_some() {
-            false => None,
-            true => Some(Vector3::new(
-                args.x_rotation.unwrap_or(0f32).to_radians(),
-                args.y_rotation.unwrap_or(0f32).to_radians(),
-                args.z_rotation.unwrap_or(0f32).to_radians(),
-            )),
-        };
+    let extension = match input.is_dir() {
+        // Directories are image slice stacks
+        true => String::new(),
+        false => input
+            .extension()
+            .expect("Input path doesn't have a extension")
+            .to_string_lossy()
+            .to_lowercase(),
+    };
+
+    let input_frame = args.input_frame.unwrap_or(match extension.as_str() {
+        "gltf" | "glb" => Frame::YUp,
+        _ => Frame::ZUp,
+    });
+
+    let transform = transform(&args, input_frame);
 
-    let extension = input
-        .extension()
-        .expect("Input path doesn't have a extension")
-        .to_string_lossy()
-        .to_string();
+    let pose = match (args.pose, &args.animation, args.time) {
+        (Some(PoseArg::Rest), _, _) => Pose::Rest,
+        (Some(PoseArg::Bind), _, _) | (None, None, None) => Pose::Bind,
+        (None, animation, time) => Pose::Animation {
+            animation: animation.clone(),
+            time: time.unwrap_or(0f32),
+        },
+    };
 
-    println!("Using {} resolution", args.resolution);
+    let options = GltfOptions {
+        pose,
+        morph_weights: args.morph_weights.clone(),
+        scene: args
+            .scene
+            .as_deref()
+            .map(SceneSelection::parse)
+            .unwrap_or_default(),
+        filter: NodeFilter {
+            include: args.include_node.clone(),
+            exclude: args.exclude_node.clone(),
+            include_extras: args.include_extras.clone(),
+            exclude_extras: args.exclude_extras.clone(),
+            skip_helpers: args.skip_helpers,
+        },
+    };
+
+    let size = match (&args.resolution, args.fit, &args.fit_box) {
+        (Some(resolution), _, _) => match resolution.as_slice() {
+            [r] => Size::Resolution(Vector3::repeat(*r)),
+            [x, y, z] => Size::Resolution(Vector3::new(*x, *y, *z)),
+            _ => panic!("--resolution expects 1 or 3 comma separated values"),
+        },
+        (_, Some(voxels), _) => Size::Fit(voxels, args.fit_axis),
+        (_, _, Some(voxels)) => match voxels.as_slice() {
+            [x, y, z] => Size::FitBox(Vector3::new(*x, *y, *z)),
+            _ => panic!("--fit-box expects 3 comma separated values"),
+        },
+        _ => unreachable!(),
+    };
+
+    let grid_offset = match args.grid_offset.as_deref() {
+        None => Vector3::zeros(),
+        Some([x, y, z]) => Vector3::new(*x, *y, *z),
+        Some(_) => panic!("--grid-offset expects 3 comma separated values"),
+    };
+
+    let local_origin = args
+        .local_origin
+        .as_deref()
+        .map(|value| {
+            // Fitted voxel sizes depend on the bounding box, unknown here
+            let step = match &size {
+                Size::Resolution(resolution) => resolution.cast(),
+                _ => Vector3::repeat(1.0),
+            };
+
+            // The offset goes through the linear part of the transforms
+            let linear = transform
+                .map(|m| m.fixed_view::<3, 3>(0, 0).into_owned().cast::<f64>())
+                .unwrap_or_else(Matrix3::identity);
+
+            LocalOrigin::parse(value, step, linear)
+        })
+        .unwrap_or_default();
+
+    let settings = Settings {
+        size,
+        anchor: args.anchor,
+        grid_offset,
+        convention: args.lattice,
+        local_origin,
+        point_filter: PointFilter {
+            min_points: args.min_points,
+            statistical: args.outlier_neighbors.map(|k| (k, args.outlier_std_ratio)),
+            radius: args.outlier_radius.map(|r| (r, args.outlier_min_neighbors)),
+        },
+        sensor_origin: args.sensor_origin.as_deref().map(|origin| match origin {
+            [x, y, z] => Vector3::new(*x, *y, *z),
+            _ => panic!("--sensor-origin expects 3 comma separated values"),
+        }),
+        carving: args
+            .free_space
+            .then(|| Carving::new(args.prob_hit, args.prob_miss, args.max_range)),
+        output_frame: args.output_frame,
+        transform,
+        split: args.split,
+        materials: args.materials,
+        counts: args.counts,
+    };
+
+    let las_options = LasOptions {
+        classes: args.classes.clone(),
+        exclude_classes: args.exclude_classes.clone(),
+        color: args.las_color,
+    };
+
+    let text_options = TextOptions {
+        columns: args
+            .columns
+            .as_ref()
+            .map(|columns| columns.iter().map(|c| Column::parse(c)).collect()),
+        delimiter: args.delimiter,
+    };
+
+    let colmap_options = ColmapOptions {
+        min_track_length: args.min_track_length,
+        max_error: args.max_reprojection_error,
+    };
+
+    let transfer = Transfer {
+        threshold: args.threshold,
+        window: args.window.zip(args.level),
+    };
 
     match extension.as_str() {
+        _ if input.is_dir() => {
+            slices(
+                input,
+                output,
+                &settings,
+                &transfer,
+                args.pixel_size,
+                args.slice_spacing,
+            );
+        }
         "gltf" | "glb" => {
-            gltf(input, output, args.resolution, rotation);
+            if args.local_origin.is_some() {
+                eprintln!("Meshes are read in single precision, --local-origin is ignored");
+            }
+
+            match args.frame_rate {
+                Some(frame_rate) => {
+                    gltf_frames(input, output, &settings, &options, frame_rate);
+                }
+                None => {
+                    gltf(input, output, &settings, &options);
+                }
+            }
+        }
+        "ply" => match is_splat_ply(&input) {
+            true => {
+                splats(
+                    input,
+                    output,
+                    &settings,
+                    args.splat_mode,
+                    args.min_opacity,
+                    args.splat_sigma,
+                    args.max_splat_size,
+                );
+            }
+            false => {
+                ply(input, output, &settings, &colmap_options);
+            }
+        },
+        "las" | "laz" => {
+            las(input, output, &settings, &las_options);
+        }
+        "txt" | "bin" if is_colmap_points(&input) => {
+            colmap(input, output, &settings, &colmap_options);
         }
-        "ply" => {
-            ply(input, output, args.resolution, rotation);
+        "xyz" | "txt" | "asc" => {
+            text(input, output, &settings, TextFormat::Xyz, &text_options);
+        }
+        "pts" => {
+            text(input, output, &settings, TextFormat::Pts, &text_options);
+        }
+        "csv" => {
+            text(input, output, &settings, TextFormat::Csv, &text_options);
+        }
+        "pcd" => {
+            pcd(input, output, &settings);
+        }
+        "e57" => {
+            e57(input, output, &settings);
+        }
+        extension if IMAGE_EXTENSIONS.contains(&extension) => {
+            let image_input = args
+                .image_input
+                .unwrap_or(match is_multi_page_tiff(&input) {
+                    true => ImageInput::Slices,
+                    false => ImageInput::Heightmap,
+                });
+
+            match image_input {
+                ImageInput::Heightmap => {
+                    heightmap(
+                        input,
+                        output,
+                        &settings,
+                        args.fill,
+                        args.pixel_size,
+                        args.height_scale,
+                        args.color_map.as_deref().map(Path::new),
+                    );
+                }
+                ImageInput::Sprite => {
+                    sprite(
+                        input,
+                        output,
+                        &settings,
+                        args.back_sprite.as_deref().map(Path::new),
+                        args.side_sprite.as_deref().map(Path::new),
+                        args.sprite_depth,
+                        args.pixel_size,
+                    );
+                }
+                ImageInput::Slices => {
+                    slices(
+                        input,
+                        output,
+                        &settings,
+                        &transfer,
+                        args.pixel_size,
+                        args.slice_spacing,
+                    );
+                }
+            }
+        }
+        "sdf" | "json" => {
+            sdf(input, output, &settings, args.fill);
+        }
+        "rgbd" => {
+            rgbd(input, output, &settings, args.truncation, args.max_depth);
         }
         _ => {
             eprintln!("Unrecognized extension '{}'", extension);
@@ -76,28 +771,509 @@ fn main() {
     }
 }
 
-fn ply(input: PathBuf, output: PathBuf, resolution: f32, rotation: Option<Vector3<f32>>) {
+/// Combines the frame and unit conversion with the user transforms, which are
+/// expressed in the output frame
+fn transform(args: &Args, input_frame: Frame) -> Option<Matrix4<f32>> {
+    let vector = |values: &Vec<f32>, name: &str| match values.as_slice() {
+        [x, y, z] => Vector3::new(*x, *y, *z),
+        _ => panic!("--{} expects 3 comma separated values", name),
+    };
+
+    let transform = Transform {
+        matrix: args.matrix.as_ref().map(|values| match values.len() {
+            16 => Matrix4::from_row_slice(values),
+            _ => panic!("--matrix expects 16 comma separated values"),
+        }),
+        scale: args.scale.as_ref().map(|values| match values.as_slice() {
+            [s] => Vector3::new(*s, *s, *s),
+            _ => vector(values, "scale"),
+        }),
+        euler: match args.x_rotation.is_some()
+            || args.y_rotation.is_some()
+            || args.z_rotation.is_some()
+        {
+            false => None,
+            true => Some(Vector3::new(
+                args.x_rotation.unwrap_or(0f32).to_radians(),
+                args.y_rotation.unwrap_or(0f32).to_radians(),
+                args.z_rotation.unwrap_or(0f32).to_radians(),
+            )),
+        },
+        quaternion: args
+            .quaternion
+            .as_ref()
+            .map(|values| match values.as_slice() {
+                [x, y, z, w] => UnitQuaternion::from_quaternion(Quaternion::new(*w, *x, *y, *z)),
+                _ => panic!("--quaternion expects 4 comma separated values"),
+            }),
+        axis_angle: args
+            .axis_angle
+            .as_ref()
+            .map(|values| match values.as_slice() {
+                [x, y, z, angle] => (
+                    Unit::new_normalize(Vector3::new(*x, *y, *z)),
+                    angle.to_radians(),
+                ),
+                _ => panic!("--axis-angle expects 4 comma separated values"),
+            }),
+        translation: args
+            .translate
+            .as_ref()
+            .map(|values| vector(values, "translate")),
+    };
+
+    let matrix = transform.matrix() * conversion(input_frame, args.output_frame, args.units);
+
+    match matrix == Matrix4::identity() {
+        true => None,
+        false => Some(matrix),
+    }
+}
+
+/// Voxelization settings shared by every input format
+struct Settings {
+    size: Size,
+    anchor: Anchor,
+    grid_offset: Vector3<f32>,
+    convention: Convention,
+    local_origin: LocalOrigin,
+    point_filter: PointFilter,
+    /// Global sensor position shared by every point
+    sensor_origin: Option<Vector3<f64>>,
+    carving: Option<Carving>,
+    output_frame: Frame,
+    /// Transform applied to the geometry, `None` when it's the identity
+    transform: Option<Matrix4<f32>>,
+    split: Option<Split>,
+    materials: Option<MaterialPriority>,
+    counts: Option<Counts>,
+}
+
+impl Settings {
+    /// Lattice for geometry spanning `bbox`, after transforms
+    fn lattice(&self, bbox: &Bbox) -> Lattice {
+        let anchor = match self.anchor {
+            Anchor::World => Vector3::zeros(),
+            Anchor::Min => bbox.min,
+            Anchor::Center => (bbox.min + bbox.max) / 2.0,
+            Anchor::Ground => {
+                let mut anchor = Vector3::zeros();
+                anchor[self.output_frame.up()] = bbox.min[self.output_frame.up()];
+                anchor
+            }
+        };
+
+        let origin = anchor + self.grid_offset;
+        let resolution = self.size.resolve(bbox, &origin, self.convention);
+
+        match resolution.x == resolution.y && resolution.y == resolution.z {
+            true => println!("Using {} resolution", resolution.x),
+            false => println!(
+                "Using {}, {}, {} resolution",
+                resolution.x, resolution.y, resolution.z
+            ),
+        }
+
+        Lattice::new(resolution, origin, self.convention)
+    }
+}
+
+fn ply(input: PathBuf, output: PathBuf, settings: &Settings, options: &ColmapOptions) {
+    // Points of a COLMAP dense reconstruction seen by enough images, indexed
+    // like the vertices of the ply
+    let keep = match options.min_track_length > 1 {
+        true => match fused_track_lengths(&input) {
+            Some(track_lengths) => Some(
+                track_lengths
+                    .iter()
+                    .map(|length| *length >= options.min_track_length)
+                    .collect::<Vec<bool>>(),
+            ),
+            None => {
+                eprintln!(
+                    "No '{}.vis' visibility file, --min-track-length is ignored",
+                    input.display()
+                );
+                None
+            }
+        },
+        false => None,
+    };
+
     let start = Instant::now();
-    let mut pointcloud = load_ply(&input);
+    let pointcloud = load_ply(&input, &settings.local_origin, keep.as_deref());
 
-    if let Some(rotation) = rotation {
-        pointcloud.rotate(rotation);
+    println!(
+        "Loaded '{}' in {:.3}s",
+        input.display(),
+        start.elapsed().as_secs_f32()
+    );
+
+    if let Some(keep) = &keep {
+        let removed = keep.iter().filter(|keep| !**keep).count();
+        println!("Dropped {} points below --min-track-length", removed);
     }
 
+    voxelize_pointcloud(pointcloud, &output, settings);
+}
+
+fn splats(
+    input: PathBuf,
+    output: PathBuf,
+    settings: &Settings,
+    mode: SplatMode,
+    min_opacity: f32,
+    sigma: f32,
+    max_size: u32,
+) {
+    let start = Instant::now();
+    let (mut splats, filtered) = load_splats(&input, min_opacity, &settings.local_origin);
+
     println!(
         "Loaded '{}' in {:.3}s",
         input.display(),
         start.elapsed().as_secs_f32()
     );
 
-    let bar = ProgressBar::new(0)
-        .with_style(
-            ProgressStyle::with_template("[{elapsed_precise}] {bar:50} {pos}/{len} {msg}").unwrap(),
-        )
-        .with_message("- Voxelizing...");
+    if filtered > 0 {
+        println!("Dropped {} splats below --min-opacity", filtered);
+    }
+
+    if mode == SplatMode::Centers {
+        voxelize_pointcloud(splats.centers(), &output, settings);
+        return;
+    }
+
+    if let Some(transform) = &settings.transform {
+        splats.transform(transform);
+    }
+
+    let lattice = settings.lattice(&splats.bbox());
+
+    let bar = progress_bar(0, "- Voxelizing...");
+
+    let start = Instant::now();
+    let (voxels, counts, skipped) = splats.voxelize(&lattice, sigma, max_size, &bar);
+
+    drop(bar);
+
+    println!("Voxelized splats in {:.3}s", start.elapsed().as_secs_f64());
+
+    if skipped > 0 {
+        println!(
+            "Skipped {} splats --max-splat-size voxels wide or more, or with non-finite axes",
+            skipped
+        );
+    }
+
+    let mut channels = Channels::default();
+    if let Some(mode) = settings.counts {
+        channels.set_counts(counts, mode);
+    }
+
+    save(
+        &output,
+        &voxels,
+        &channels,
+        &Metadata::new(&lattice, splats.offset()),
+    );
+}
+
+fn colmap(input: PathBuf, output: PathBuf, settings: &Settings, options: &ColmapOptions) {
+    let start = Instant::now();
+    let (pointcloud, filtered) = load_colmap_points(&input, options, &settings.local_origin);
+
+    println!(
+        "Loaded '{}' in {:.3}s",
+        input.display(),
+        start.elapsed().as_secs_f32()
+    );
+
+    if filtered > 0 {
+        println!(
+            "Dropped {} points by track length or reprojection error",
+            filtered
+        );
+    }
+
+    voxelize_pointcloud(pointcloud, &output, settings);
+}
+
+fn las(input: PathBuf, output: PathBuf, settings: &Settings, options: &LasOptions) {
+    let start = Instant::now();
+    let (pointcloud, filtered) = load_las(&input, options, &settings.local_origin);
+
+    println!(
+        "Loaded '{}' in {:.3}s",
+        input.display(),
+        start.elapsed().as_secs_f32()
+    );
+
+    if filtered > 0 {
+        println!("Filtered out {} points by classification", filtered);
+    }
+
+    voxelize_pointcloud(pointcloud, &output, settings);
+}
+
+fn text(
+    input: PathBuf,
+    output: PathBuf,
+    settings: &Settings,
+    format: TextFormat,
+    options: &TextOptions,
+) {
+    let start = Instant::now();
+    let pointcloud = load_text(&input, format, options, &settings.local_origin);
+
+    println!(
+        "Loaded '{}' in {:.3}s",
+        input.display(),
+        start.elapsed().as_secs_f32()
+    );
+
+    voxelize_pointcloud(pointcloud, &output, settings);
+}
+
+fn pcd(input: PathBuf, output: PathBuf, settings: &Settings) {
+    let start = Instant::now();
+    let pointcloud = load_pcd(&input, &settings.local_origin);
+
+    println!(
+        "Loaded '{}' in {:.3}s",
+        input.display(),
+        start.elapsed().as_secs_f32()
+    );
+
+    voxelize_pointcloud(pointcloud, &output, settings);
+}
+
+fn e57(input: PathBuf, output: PathBuf, settings: &Settings) {
+    let start = Instant::now();
+    let pointcloud = load_e57(&input, &settings.local_origin);
+
+    println!(
+        "Loaded '{}' in {:.3}s",
+        input.display(),
+        start.elapsed().as_secs_f32()
+    );
+
+    voxelize_pointcloud(pointcloud, &output, settings);
+}
+
+fn heightmap(
+    input: PathBuf,
+    output: PathBuf,
+    settings: &Settings,
+    fill: Fill,
+    pixel_size: f32,
+    height_scale: f32,
+    color_map: Option<&Path>,
+) {
+    let start = Instant::now();
+    let heightmap = load_heightmap(&input, pixel_size, height_scale, color_map).with_fill(fill);
+
+    println!(
+        "Loaded '{}' in {:.3}s",
+        input.display(),
+        start.elapsed().as_secs_f32()
+    );
+
+    voxelize_sampled(&heightmap, "terrain", &output, settings);
+}
+
+fn slices(
+    input: PathBuf,
+    output: PathBuf,
+    settings: &Settings,
+    transfer: &Transfer,
+    pixel_size: f32,
+    slice_spacing: f32,
+) {
+    let start = Instant::now();
+    let volume = load_slices(&input, pixel_size, slice_spacing).with_transfer(*transfer);
+
+    println!(
+        "Loaded {} slices from '{}' in {:.3}s",
+        volume.slices(),
+        input.display(),
+        start.elapsed().as_secs_f32()
+    );
+
+    voxelize_sampled(&volume, "slices", &output, settings);
+}
+
+fn sprite(
+    input: PathBuf,
+    output: PathBuf,
+    settings: &Settings,
+    back: Option<&Path>,
+    side: Option<&Path>,
+    depth: Option<u32>,
+    pixel_size: f32,
+) {
+    let start = Instant::now();
+    let sprite = load_sprite(&input, back, side, depth, pixel_size);
+
+    println!(
+        "Loaded '{}' in {:.3}s",
+        input.display(),
+        start.elapsed().as_secs_f32()
+    );
+
+    voxelize_sampled(&sprite, "sprite", &output, settings);
+}
+
+fn sdf(input: PathBuf, output: PathBuf, settings: &Settings, fill: Fill) {
+    let scene = load_sdf(&input).with_fill(fill);
+
+    println!("Loaded '{}'", input.display());
+
+    voxelize_sampled(&scene, "scene", &output, settings);
+}
+
+/// Places geometry sampled at voxel centres with the transforms, then
+/// voxelizes and saves it
+fn voxelize_sampled(source: &impl Sampled, name: &str, output: &Path, settings: &Settings) {
+    let transform = settings.transform.unwrap_or_else(Matrix4::identity);
+
+    let lattice = settings.lattice(&source.bbox().transform(&transform));
+
+    let bar = progress_bar(0, "- Voxelizing...");
+
+    let start = Instant::now();
+    let voxels = source.voxelize(&lattice, &transform, &bar);
+
+    drop(bar);
+
+    println!(
+        "Voxelized {} in {:.3}s",
+        name,
+        start.elapsed().as_secs_f64()
+    );
+
+    save(
+        output,
+        &voxels,
+        &Channels::default(),
+        &Metadata::new(&lattice, Vector3::zeros()),
+    );
+}
+
+/// Fuses the depth maps of an rgbd sequence into a truncated signed distance
+/// grid and keeps the voxels on its surface
+fn rgbd(
+    input: PathBuf,
+    output: PathBuf,
+    settings: &Settings,
+    truncation: Option<f32>,
+    max_depth: Option<f32>,
+) {
+    let sequence = load_rgbd(&input);
+
+    println!(
+        "Loaded '{}' with {} frames",
+        input.display(),
+        sequence.frames.len()
+    );
+
+    let poses = sequence
+        .frames
+        .iter()
+        .map(|frame| match &settings.transform {
+            Some(transform) => transform * frame.pose,
+            None => frame.pose,
+        })
+        .collect::<Vec<Matrix4<f32>>>();
+
+    let bar = progress_bar(sequence.frames.len() as u64, "- Measuring...");
+
+    // The lattice depends on the extent of every frame
+    let bbox = sequence
+        .frames
+        .iter()
+        .zip(&poses)
+        .filter_map(|(frame, pose)| {
+            let depth = frame.load_depth(sequence.depth_scale);
+            bar.inc(1);
+            depth_bbox(&depth, &sequence.intrinsics, pose, max_depth)
+        })
+        .reduce(|a, b| a.union(&b))
+        .expect("Rgbd sequence has no valid depth");
+
+    let lattice = settings.lattice(&bbox);
+    let truncation = truncation.unwrap_or(3.0 * lattice.resolution.max());
+
+    bar.reset();
+    bar.set_message("- Fusing...");
+
+    let start = Instant::now();
+    let mut tsdf = Tsdf::new(lattice, truncation, max_depth);
+
+    for (frame, pose) in sequence.frames.iter().zip(&poses) {
+        let depth = frame.load_depth(sequence.depth_scale);
+        let color = frame.load_color(&depth);
+
+        tsdf.integrate(&depth, color.as_ref(), &sequence.intrinsics, pose);
+        bar.inc(1);
+    }
+
+    let voxels = tsdf.extract();
+
+    drop(bar);
+
+    println!("Fused depth maps in {:.3}s", start.elapsed().as_secs_f64());
+
+    save(
+        &output,
+        &voxels,
+        &Channels::default(),
+        &Metadata::new(&lattice, Vector3::zeros()),
+    );
+}
+
+/// Transforms, filters and voxelizes a loaded point cloud
+fn voxelize_pointcloud(mut pointcloud: PointCloud, output: &Path, settings: &Settings) {
+    if let Some(origin) = settings.sensor_origin {
+        pointcloud.set_sensor_origin(origin);
+    }
+
+    if let Some(transform) = &settings.transform {
+        pointcloud.transform(transform);
+    }
+
+    filter_pointcloud(&mut pointcloud, &settings.point_filter);
+
+    let lattice = settings.lattice(&pointcloud.bbox());
+
+    let bar = progress_bar(0, "- Voxelizing...");
+
+    if let Some(carving) = &settings.carving {
+        let start = Instant::now();
+        let grid = pointcloud.carve(&lattice, carving, &bar);
+
+        drop(bar);
+
+        println!("Carved free space in {:.3}s", start.elapsed().as_secs_f64());
+
+        let channels = Channels {
+            occupancy: Some(grid.probabilities),
+            states: Some(grid.states),
+            ..Default::default()
+        };
+
+        save(
+            output,
+            &grid.voxels,
+            &channels,
+            &Metadata::new(&lattice, pointcloud.offset()),
+        );
+
+        return;
+    }
 
     let start = Instant::now();
-    let voxels = pointcloud.voxelize(resolution, &bar);
+    let (voxels, counts) = pointcloud.voxelize(&lattice, settings.point_filter.min_points, &bar);
 
     drop(bar);
 
@@ -106,52 +1282,213 @@ fn ply(input: PathBuf, output: PathBuf, resolution: f32, rotation: Option<Vector
         start.elapsed().as_secs_f64()
     );
 
+    let removed = pointcloud.len() - counts.iter().map(|c| *c as usize).sum::<usize>();
+    if removed > 0 {
+        println!("Dropped {} points in voxels below --min-points", removed);
+    }
+
+    let mut channels = Channels::default();
+    if let Some(mode) = settings.counts {
+        channels.set_counts(counts, mode);
+    }
+
+    save(
+        output,
+        &voxels,
+        &channels,
+        &Metadata::new(&lattice, pointcloud.offset()),
+    );
+}
+
+/// Runs the outlier removal filters, reporting the removed points
+fn filter_pointcloud(pointcloud: &mut PointCloud, filter: &PointFilter) {
+    if let Some((k, std_ratio)) = filter.statistical {
+        let start = Instant::now();
+        let removed = pointcloud.remove_statistical_outliers(k, std_ratio);
+
+        println!(
+            "Removed {} statistical outliers in {:.3}s",
+            removed,
+            start.elapsed().as_secs_f32()
+        );
+    }
+
+    if let Some((radius, min_neighbors)) = filter.radius {
+        let start = Instant::now();
+        let removed = pointcloud.remove_radius_outliers(radius, min_neighbors);
+
+        println!(
+            "Removed {} radius outliers in {:.3}s",
+            removed,
+            start.elapsed().as_secs_f32()
+        );
+    }
+}
+
+fn gltf(input: PathBuf, output: PathBuf, settings: &Settings, options: &GltfOptions) {
     let start = Instant::now();
-    save_voxels(&output, &voxels);
+    let mut meshes = load_gltf(&input, options);
     println!(
-        "Saved {} voxels in file '{}' in {:.3}s!",
-        voxels.len(),
-        output.display(),
+        "Loaded '{}' in {:.3}s",
+        input.display(),
         start.elapsed().as_secs_f32()
     );
+
+    transform_meshes(&mut meshes, settings);
+
+    let lattice = settings.lattice(&Bbox::from_meshes(&meshes));
+
+    save_meshes(meshes, &output, settings, &lattice);
 }
 
-fn gltf(input: PathBuf, output: PathBuf, resolution: f32, rotation: Option<Vector3<f32>>) {
+fn gltf_frames(
+    input: PathBuf,
+    output: PathBuf,
+    settings: &Settings,
+    options: &GltfOptions,
+    frame_rate: f32,
+) {
     let start = Instant::now();
-    let mut meshes = load_gltf(&input);
+    let file = GltfFile::open(&input);
     println!(
         "Loaded '{}' in {:.3}s",
         input.display(),
         start.elapsed().as_secs_f32()
     );
 
+    let animation = match &options.pose {
+        Pose::Animation { animation, .. } => animation.clone(),
+        _ => None,
+    };
+
+    let duration = file.animation_duration(animation.as_deref());
+    let frames = (duration * frame_rate).floor() as usize + 1;
+
+    println!(
+        "Sampling {} frames over {:.3}s at {} fps",
+        frames, duration, frame_rate
+    );
+
+    // Every frame uses the lattice of the first one
+    let mut lattice = None;
+
+    for frame in 0..frames {
+        let options = GltfOptions {
+            pose: Pose::Animation {
+                animation: animation.clone(),
+                time: frame as f32 / frame_rate,
+            },
+            ..options.clone()
+        };
+
+        let mut meshes = file.meshes(&options);
+
+        transform_meshes(&mut meshes, settings);
+
+        let lattice = *lattice.get_or_insert_with(|| settings.lattice(&Bbox::from_meshes(&meshes)));
+
+        save_meshes(meshes, &numbered_path(&output, frame), settings, &lattice);
+    }
+}
+
+/// Voxelizes meshes into a single output, or into one output per node or
+/// material when splitting. Split outputs are voxelized on the same lattice so
+/// they share the same origin and can be reassembled
+fn save_meshes(mut meshes: Vec<Mesh>, output: &Path, settings: &Settings, lattice: &Lattice) {
+    let groups = match settings.split {
+        None => vec![(output.to_path_buf(), meshes)],
+        Some(split) => {
+            let mut groups: Vec<(Option<usize>, String, Vec<Mesh>)> = Vec::new();
+
+            for mesh in meshes.drain(..) {
+                let source = mesh.source();
+
+                let (index, name) = match split {
+                    Split::Node => (source.node_index, &source.node),
+                    Split::Material => (source.material_index, &source.material),
+                };
+
+                let name = sanitize(name.as_deref().unwrap_or_default());
+
+                match groups.iter_mut().find(|(i, ..)| *i == index) {
+                    Some((.., group)) => group.push(mesh),
+                    None => groups.push((index, name, vec![mesh])),
+                }
+            }
+
+            // Empty names and names shared by several groups get the index
+            let names = groups
+                .iter()
+                .map(|(_, name, _)| name.clone())
+                .collect::<Vec<String>>();
+
+            groups
+                .into_iter()
+                .map(|(index, name, group)| {
+                    let shared = names.iter().filter(|n| **n == name).count() > 1;
+
+                    let name = match index {
+                        Some(index) if name.is_empty() => index.to_string(),
+                        Some(index) if shared => format!("{}_{}", name, index),
+                        None if name.is_empty() => "unnamed".to_string(),
+                        _ => name,
+                    };
+
+                    (suffixed_path(output, &name), group)
+                })
+                .collect()
+        }
+    };
+
+    for (output, meshes) in groups {
+        let (voxels, channels) = voxelize_meshes(&meshes, settings, lattice);
+
+        save(
+            &output,
+            &voxels,
+            &channels,
+            &Metadata::new(lattice, Vector3::zeros()),
+        );
+    }
+}
+
+fn transform_meshes(meshes: &mut [Mesh], settings: &Settings) {
+    if let Some(transform) = &settings.transform {
+        for mesh in meshes.iter_mut() {
+            mesh.transform(transform);
+        }
+    }
+}
+
+fn voxelize_meshes(
+    meshes: &[Mesh],
+    settings: &Settings,
+    lattice: &Lattice,
+) -> (Vec<Voxel>, Channels) {
     let start = Instant::now();
     let mut voxels = Vec::new();
 
     let bars = MultiProgress::new();
 
-    let scene_bar = ProgressBar::new(meshes.len() as u64)
-        .with_style(
-            ProgressStyle::with_template("[{elapsed_precise}] {bar:50} {pos}/{len} {msg}").unwrap(),
-        )
-        .with_message("- Scene");
+    let scene_bar = progress_bar(meshes.len() as u64, "- Scene");
 
     let scene_bar = bars.add(scene_bar);
 
-    let mesh_bar = ProgressBar::new(0)
-        .with_style(
-            ProgressStyle::with_template("[{elapsed_precise}] {bar:50} {pos}/{len} {msg}").unwrap(),
-        )
-        .with_message("- Mesh");
+    let mesh_bar = progress_bar(0, "- Mesh");
 
     let mesh_bar = bars.add(mesh_bar);
 
-    for mesh in meshes.iter_mut() {
-        if let Some(rotation) = rotation {
-            mesh.rotate(rotation);
-        }
+    for mesh in meshes {
+        let material = mesh
+            .source()
+            .material_index
+            .map_or(NO_MATERIAL, |m| m as u32);
 
-        voxels.append(&mut mesh.voxelize_shell(resolution, &mesh_bar));
+        voxels.extend(
+            mesh.voxelize_shell(lattice, &mesh_bar)
+                .into_iter()
+                .map(|(position, color)| (position, color, material)),
+        );
 
         scene_bar.inc(1);
     }
@@ -162,15 +1499,95 @@ fn gltf(input: PathBuf, output: PathBuf, resolution: f32, rotation: Option<Vecto
     println!("Voxelized scene in {:.3}s", start.elapsed().as_secs_f64());
 
     let start = Instant::now();
-    let voxels = HashMap::from_iter(voxels);
-    let voxels = voxels.into_iter().collect::<Vec<(Vector3<i32>, [u8; 4])>>();
+    let (voxels, mut channels, counts) = match settings.materials {
+        Some(priority) => {
+            let (voxels, materials, counts) = deduplicate_materials(voxels, priority);
+
+            let mut material_names = meshes
+                .iter()
+                .filter_map(|mesh| {
+                    let source = mesh.source();
+                    Some((source.material_index? as u32, source.material.clone()?))
+                })
+                .collect::<Vec<(u32, String)>>();
+
+            material_names.sort();
+            material_names.dedup();
+
+            let channels = Channels {
+                materials: Some(materials),
+                material_names,
+                ..Default::default()
+            };
+
+            (voxels, channels, counts)
+        }
+        None => {
+            let (voxels, counts) =
+                deduplicate(voxels.into_iter().map(|(p, c, _)| (p, c)).collect());
+
+            (voxels, Channels::default(), counts)
+        }
+    };
+
+    if let Some(mode) = settings.counts {
+        channels.set_counts(counts, mode);
+    }
+
     println!(
         "Deduplicated voxels in {:.3}s",
         start.elapsed().as_secs_f64()
     );
 
+    (voxels, channels)
+}
+
+/// Saves voxels in the format matching the output extension, ply outputs
+/// keep the extra channels and the metadata. Other outputs are saved in the
+/// voxel format, with the metadata in a json file next to them unless the
+/// voxels are unit cubes from the world origin
+fn save(
+    output: &Path,
+    voxels: &[(Vector3<i32>, [u8; 4])],
+    channels: &Channels,
+    metadata: &Metadata,
+) {
     let start = Instant::now();
-    save_voxels(&output, &voxels);
+
+    match output.extension().and_then(|e| e.to_str()) {
+        Some(extension) if extension.eq_ignore_ascii_case("ply") => {
+            save_voxels_ply(output, voxels, channels, metadata)
+        }
+        _ => {
+            if channels.materials.is_some() {
+                eprintln!("Voxel format doesn't store materials, use a .ply output to keep them");
+            }
+
+            if channels.counts.is_some() || channels.occupancy.is_some() {
+                eprintln!("Voxel format doesn't store counts, use a .ply output to keep them");
+            }
+
+            if channels.states.is_some() {
+                eprintln!("Voxel format doesn't store voxel states, free voxels are transparent");
+            }
+
+            save_voxels(output, voxels);
+
+            // Unit voxels from the world origin need no metadata
+            if !metadata.is_identity() {
+                let path = output.with_extension("json");
+
+                match path == output || (path.exists() && !Metadata::is_saved(&path)) {
+                    true => eprintln!("Metadata would overwrite '{}', skipped", path.display()),
+                    false => {
+                        metadata.save(&path);
+                        println!("Saved metadata in file '{}'", path.display());
+                    }
+                }
+            }
+        }
+    }
+
     println!(
         "Saved {} voxels in file '{}' in {:.3}s!",
         voxels.len(),
@@ -178,3 +1595,48 @@ fn gltf(input: PathBuf, output: PathBuf, resolution: f32, rotation: Option<Vecto
         start.elapsed().as_secs_f32()
     );
 }
+
+/// Appends a zero padded frame number to the file name, `out.bin` becomes
+/// `out_0003.bin`
+fn numbered_path(path: &Path, index: usize) -> PathBuf {
+    suffixed_path(path, &format!("{:04}", index))
+}
+
+/// Replaces the characters that aren't safe in file names
+fn sanitize(name: &str) -> String {
+    name.chars()
+        .map(|c| match c.is_alphanumeric() || c == '-' || c == '_' {
+            true => c,
+            false => '_',
+        })
+        .collect()
+}
+
+fn suffixed_path(path: &Path, suffix: &str) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+
+    let name = match path.extension() {
+        Some(extension) => format!("{}_{}.{}", stem, suffix, extension.to_string_lossy()),
+        None => format!("{}_{}", stem, suffix),
+    };
+
+    path.with_file_name(name)
+}
+
+/// Progress bar of `length` steps, with the elapsed time and a message
+fn progress_bar(length: u64, message: &'static str) -> ProgressBar {
+    ProgressBar::new(length)
+        .with_style(
+            ProgressStyle::with_template("[{elapsed_precise}] {bar:50} {pos}/{len} {msg}").unwrap(),
+        )
+        .with_message(message)
+}
+
+/// Parses a finite number above 0
+fn positive(value: &str) -> Result<f32, String> {
+    match value.parse::<f32>() {
+        Ok(v) if v.is_finite() && v > 0.0 => Ok(v),
+        Ok(_) => Err(String::from("should be a finite number above 0")),
+        Err(e) => Err(e.to_string()),
+    }
+}
@@ -1,11 +1,11 @@
 use bvh::{bounding_hierarchy::BoundingHierarchy, bvh::Bvh, ray::Ray};
 use image::{ImageReader, RgbaImage};
 use indicatif::ProgressBar;
-use nalgebra::{OPoint, SVector, Vector2, Vector3};
+use nalgebra::{Matrix4, OPoint, Point3, SVector, Vector2, Vector3};
 use texture::Texture;
 use triangle::Triangle;
 
-use crate::bbox::Bbox;
+use crate::{bbox::Bbox, lattice::Lattice};
 
 pub mod texture;
 pub mod triangle;
@@ -15,6 +15,16 @@ pub struct Mesh {
     bvh: Bvh<f32, 3>,
     bbox: Bbox,
     texture: Option<Texture>,
+    source: MeshSource,
+}
+
+/// Where a mesh comes from in its file
+#[derive(Clone, Debug, Default)]
+pub struct MeshSource {
+    pub node: Option<String>,
+    pub node_index: Option<usize>,
+    pub material: Option<String>,
+    pub material_index: Option<usize>,
 }
 
 impl Mesh {
@@ -47,6 +57,7 @@ impl Mesh {
             triangles,
             texture,
             bbox: Bbox::new(Vector3::zeros(), Vector3::zeros()),
+            source: MeshSource::default(),
         };
 
         mesh.bbox = Bbox::from_mesh(&mesh);
@@ -54,14 +65,15 @@ impl Mesh {
         mesh
     }
 
-    pub fn rotate(&mut self, rotation: Vector3<f32>) {
-        let rotation_matrix =
-            nalgebra::Rotation3::from_euler_angles(rotation.x, rotation.y, rotation.z);
+    /// Applies an affine transform to every vertex, rebuilding the bvh and
+    /// the bounding box once
+    pub fn transform(&mut self, matrix: &Matrix4<f32>) {
+        let transform = |p: Vector3<f32>| matrix.transform_point(&Point3::from(p)).coords;
 
         for triangle in &mut self.triangles {
-            triangle.position_a = rotation_matrix * triangle.position_a;
-            triangle.position_b = rotation_matrix * triangle.position_b;
-            triangle.position_c = rotation_matrix * triangle.position_c;
+            triangle.position_a = transform(triangle.position_a);
+            triangle.position_b = transform(triangle.position_b);
+            triangle.position_c = transform(triangle.position_c);
         }
 
         self.bvh = Bvh::build(&mut self.triangles);
@@ -69,25 +81,30 @@ impl Mesh {
         self.bbox = Bbox::from_mesh(self);
     }
 
+    pub fn with_source(mut self, source: MeshSource) -> Self {
+        self.source = source;
+        self
+    }
+
+    pub fn source(&self) -> &MeshSource {
+        &self.source
+    }
+
+    pub fn bbox(&self) -> &Bbox {
+        &self.bbox
+    }
+
     pub fn triangles(&self) -> &[Triangle] {
         &self.triangles
     }
 
     pub fn voxelize_shell(
         &self,
-        resolution: f32,
+        lattice: &Lattice,
         bar: &ProgressBar,
     ) -> Vec<(Vector3<i32>, [u8; 4])> {
-        let min = Vector3::new(
-            (self.bbox.min.x / resolution) as i32 - 1,
-            (self.bbox.min.y / resolution) as i32 - 1,
-            (self.bbox.min.z / resolution) as i32 - 1,
-        );
-        let max = Vector3::new(
-            (self.bbox.max.x / resolution).ceil() as i32 + 1,
-            (self.bbox.max.y / resolution).ceil() as i32 + 1,
-            (self.bbox.max.z / resolution).ceil() as i32 + 1,
-        );
+        let min = lattice.voxel(&self.bbox.min).add_scalar(-1);
+        let max = lattice.voxel(&self.bbox.max).add_scalar(2);
 
         let mut voxels = Vec::new();
 
@@ -115,11 +132,7 @@ impl Mesh {
         // Voxelizes along the x axis
         for y in min.y..max.y {
             for z in min.z..max.z {
-                let origin = Vector3::new(
-                    min.x as f32 * resolution,
-                    y as f32 * resolution,
-                    z as f32 * resolution,
-                );
+                let origin = lattice.center(&Vector3::new(min.x, y, z));
 
                 let ray = Ray::new(OPoint::from(origin), *SVector::x_axis());
 
@@ -135,7 +148,7 @@ impl Mesh {
                         };
 
                         let point = origin + Vector3::x_axis().scale(intersection.distance);
-                        voxels.push((voxel(&point, resolution), color));
+                        voxels.push((lattice.voxel(&point), color));
                     }
                 }
             }
@@ -146,11 +159,7 @@ impl Mesh {
         // Voxelizes along the y axis
         for x in min.x..max.x {
             for z in min.z..max.z {
-                let origin = Vector3::new(
-                    x as f32 * resolution,
-                    min.y as f32 * resolution,
-                    z as f32 * resolution,
-                );
+                let origin = lattice.center(&Vector3::new(x, min.y, z));
 
                 let ray = Ray::new(OPoint::from(origin), *SVector::y_axis());
 
@@ -166,7 +175,7 @@ impl Mesh {
                         };
 
                         let point = origin + Vector3::y_axis().scale(intersection.distance);
-                        voxels.push((voxel(&point, resolution), color));
+                        voxels.push((lattice.voxel(&point), color));
                     }
                 }
             }
@@ -177,11 +186,7 @@ impl Mesh {
         // Voxelizes along the z axis
         for x in min.x..max.x {
             for y in min.y..max.y {
-                let origin = Vector3::new(
-                    x as f32 * resolution,
-                    y as f32 * resolution,
-                    min.z as f32 * resolution,
-                );
+                let origin = lattice.center(&Vector3::new(x, y, min.z));
 
                 let ray = Ray::new(OPoint::from(origin), *SVector::z_axis());
 
@@ -197,7 +202,7 @@ impl Mesh {
                         };
 
                         let point = origin + Vector3::z_axis().scale(intersection.distance);
-                        voxels.push((voxel(&point, resolution), color));
+                        voxels.push((lattice.voxel(&point), color));
                     }
                 }
             }
@@ -218,10 +223,59 @@ pub fn sample_texture(image: &RgbaImage, u: f32, v: f32) -> [u8; 4] {
         .0
 }
 
-fn voxel(pos: &Vector3<f32>, resolution: f32) -> Vector3<i32> {
-    Vector3::new(
-        (pos.x / resolution).round() as i32,
-        (pos.y / resolution).round() as i32,
-        (pos.z / resolution).round() as i32,
-    )
+#[cfg(test)]
+mod tests {
+    use crate::lattice::Convention;
+
+    use super::*;
+
+    /// Triangle in the plane z = x, spanning 0 along every axis
+    fn triangle() -> Mesh {
+        let vertices = vec![
+            Vector3::new(-0.9, -0.9, -0.9),
+            Vector3::new(0.9, -0.9, 0.9),
+            Vector3::new(0.0, 0.9, 0.0),
+        ];
+
+        Mesh::new(vertices, vec![0, 1, 2], None, None)
+    }
+
+    fn sweep(convention: Convention) -> Vec<[i32; 3]> {
+        let lattice = Lattice::new(Vector3::repeat(0.5), Vector3::zeros(), convention);
+
+        let mut voxels = triangle()
+            .voxelize_shell(&lattice, &ProgressBar::hidden())
+            .into_iter()
+            .map(|(voxel, _)| voxel.into())
+            .collect::<Vec<[i32; 3]>>();
+
+        voxels.sort();
+        voxels.dedup();
+        voxels
+    }
+
+    #[test]
+    fn center_sweep() {
+        assert_eq!(
+            sweep(Convention::Center),
+            [[-1, -1, -1], [0, -1, 0], [0, 0, 0], [0, 1, 0], [1, -1, 1]]
+        );
+    }
+
+    #[test]
+    fn corner_sweep() {
+        assert_eq!(
+            sweep(Convention::Corner),
+            [
+                [-2, -2, -2],
+                [-1, -2, -1],
+                [-1, -1, -1],
+                [-1, 0, -1],
+                [0, -2, 0],
+                [0, -1, 0],
+                [0, 0, 0],
+                [1, -2, 1]
+            ]
+        );
+    }
 }
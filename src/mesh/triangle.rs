@@ -3,7 +3,7 @@ use bvh::{
     bounding_hierarchy::BHShape,
     ray::{Intersection, Ray},
 };
-use nalgebra::{OPoint, SimdValue, Vector2, Vector3};
+use nalgebra::{OPoint, Vector2, Vector3};
 
 pub struct Triangle {
     pub position_a: Vector3<f32>,
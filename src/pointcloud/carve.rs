@@ -0,0 +1,174 @@
+use ahash::{AHashMap, AHashSet};
+use indicatif::ProgressBar;
+use nalgebra::Vector3;
+
+use super::PointCloud;
+use crate::{
+    formats::voxels::{Voxel, FREE, OCCUPIED},
+    lattice::Lattice,
+};
+
+/// Occupancy mapping parameters, probabilities are stored as log-odds
+#[derive(Clone, Copy, Debug)]
+pub struct Carving {
+    /// Log-odds added to the voxel containing a point
+    pub hit: f32,
+    /// Log-odds added to the voxels crossed by a sensor ray
+    pub miss: f32,
+    /// Clamping bounds keeping voxels able to change state
+    pub min: f32,
+    pub max: f32,
+    /// Rays are cut at this distance from the sensor, their end point is
+    /// then not marked occupied
+    pub max_range: Option<f32>,
+}
+
+impl Carving {
+    /// Parameters from hit and miss probabilities, clamped to the OctoMap
+    /// defaults of 0.12 and 0.97
+    pub fn new(hit: f32, miss: f32, max_range: Option<f32>) -> Self {
+        assert!(
+            hit > 0.5 && hit < 1.0,
+            "Hit probability must be between 0.5 and 1"
+        );
+        assert!(
+            miss > 0.0 && miss < 0.5,
+            "Miss probability must be between 0 and 0.5"
+        );
+
+        Self {
+            hit: log_odds(hit),
+            miss: log_odds(miss),
+            min: log_odds(0.12),
+            max: log_odds(0.97),
+            max_range,
+        }
+    }
+}
+
+fn log_odds(probability: f32) -> f32 {
+    (probability / (1.0 - probability)).ln()
+}
+
+fn probability(log_odds: f32) -> f32 {
+    1.0 - 1.0 / (1.0 + log_odds.exp())
+}
+
+/// Voxels observed by a carving, the ones never crossed by a ray are unknown
+pub struct OccupancyGrid {
+    /// Occupied voxels with the colour of their points, free voxels are
+    /// transparent black
+    pub voxels: Vec<Voxel>,
+    /// `FREE` or `OCCUPIED` state of every voxel
+    pub states: Vec<u8>,
+    /// Occupancy probability of every voxel
+    pub probabilities: Vec<f32>,
+}
+
+impl PointCloud {
+    /// Builds an occupancy grid by tracing a ray from the sensor to every
+    /// point. Points sharing a sensor position form a scan, a scan updates
+    /// every voxel it observes once with occupied taking precedence over
+    /// free
+    pub fn carve(&self, lattice: &Lattice, carving: &Carving, bar: &ProgressBar) -> OccupancyGrid {
+        assert!(
+            self.has_sensors(),
+            "Free space carving needs a sensor origin"
+        );
+
+        // Points of every scan, in the order the sensors first appear
+        let mut scans: Vec<Vec<usize>> = Vec::new();
+        let mut scan_indices: AHashMap<[u32; 3], usize> = AHashMap::new();
+
+        for (i, sensor) in self.sensors.iter().enumerate() {
+            let key = [sensor.x.to_bits(), sensor.y.to_bits(), sensor.z.to_bits()];
+
+            let index = *scan_indices.entry(key).or_insert_with(|| {
+                scans.push(Vec::new());
+                scans.len() - 1
+            });
+
+            scans[index].push(i);
+        }
+
+        // Log-odds and colour of every observed voxel
+        let mut grid: AHashMap<Vector3<i32>, (f32, [u8; 4])> = AHashMap::new();
+
+        let mut free = AHashSet::new();
+        let mut occupied = AHashMap::new();
+
+        bar.set_length(self.points.len() as u64);
+
+        for scan in scans {
+            free.clear();
+            occupied.clear();
+
+            for i in scan {
+                let sensor = self.sensors[i];
+                let (point, color) = self.points[i];
+
+                let (end, hit) = match carving.max_range {
+                    Some(range) if (point - sensor).norm() > range => {
+                        (sensor + (point - sensor).normalize() * range, false)
+                    }
+                    _ => (point, true),
+                };
+
+                lattice.traverse(&sensor, &end, |voxel| {
+                    free.insert(voxel);
+                });
+
+                match hit {
+                    true => {
+                        occupied.insert(lattice.voxel(&point), color);
+                    }
+                    false => {
+                        free.insert(lattice.voxel(&end));
+                    }
+                }
+
+                bar.inc(1);
+            }
+
+            for voxel in &free {
+                if occupied.contains_key(voxel) {
+                    continue;
+                }
+
+                let entry = grid.entry(*voxel).or_insert((0.0, [0u8; 4]));
+                entry.0 = (entry.0 + carving.miss).max(carving.min);
+            }
+
+            for (voxel, color) in &occupied {
+                let entry = grid.entry(*voxel).or_insert((0.0, *color));
+                entry.0 = (entry.0 + carving.hit).min(carving.max);
+                entry.1 = *color;
+            }
+        }
+
+        let mut voxels = Vec::with_capacity(grid.len());
+        let mut states = Vec::with_capacity(grid.len());
+        let mut probabilities = Vec::with_capacity(grid.len());
+
+        for (voxel, (log_odds, color)) in grid {
+            match log_odds > 0.0 {
+                true => {
+                    voxels.push((voxel, color));
+                    states.push(OCCUPIED);
+                }
+                false => {
+                    voxels.push((voxel, [0u8; 4]));
+                    states.push(FREE);
+                }
+            }
+
+            probabilities.push(probability(log_odds));
+        }
+
+        OccupancyGrid {
+            voxels,
+            states,
+            probabilities,
+        }
+    }
+}
@@ -0,0 +1,218 @@
+use ahash::AHashMap;
+use nalgebra::Vector3;
+use rayon::prelude::*;
+
+use super::PointCloud;
+
+/// Point cloud pre-filters run before voxelization
+#[derive(Clone, Copy, Debug)]
+pub struct PointFilter {
+    /// Voxels containing fewer points are dropped
+    pub min_points: usize,
+    /// Number of neighbours and standard deviation ratio of the statistical
+    /// outlier removal
+    pub statistical: Option<(usize, f32)>,
+    /// Radius and minimum number of neighbours of the radius outlier removal
+    pub radius: Option<(f32, usize)>,
+}
+
+impl Default for PointFilter {
+    fn default() -> Self {
+        Self {
+            min_points: 1,
+            statistical: None,
+            radius: None,
+        }
+    }
+}
+
+/// Points bucketed in cubic cells for neighbour queries
+struct SpatialHash<'a> {
+    points: &'a [(Vector3<f32>, [u8; 4])],
+    cell: f32,
+    cells: AHashMap<Vector3<i32>, Vec<usize>>,
+}
+
+impl<'a> SpatialHash<'a> {
+    fn new(points: &'a [(Vector3<f32>, [u8; 4])], cell: f32) -> Self {
+        let mut cells: AHashMap<Vector3<i32>, Vec<usize>> = AHashMap::new();
+
+        for (i, (p, _)) in points.iter().enumerate() {
+            cells.entry(Self::key(cell, p)).or_default().push(i);
+        }
+
+        Self {
+            points,
+            cell,
+            cells,
+        }
+    }
+
+    fn key(cell: f32, p: &Vector3<f32>) -> Vector3<i32> {
+        p.map(|v| (v / cell).floor() as i32)
+    }
+
+    /// Calls `f` with the squared distance of every other point in the cells
+    /// at Chebyshev distance `ring` from the cell of point `i`
+    fn ring(&self, i: usize, ring: i32, mut f: impl FnMut(f32)) {
+        let p = &self.points[i].0;
+        let center = Self::key(self.cell, p);
+
+        for x in -ring..=ring {
+            for y in -ring..=ring {
+                for z in -ring..=ring {
+                    if x.abs().max(y.abs()).max(z.abs()) != ring {
+                        continue;
+                    }
+
+                    let indices = match self.cells.get(&(center + Vector3::new(x, y, z))) {
+                        Some(indices) => indices,
+                        None => continue,
+                    };
+
+                    for &j in indices {
+                        if j != i {
+                            f((self.points[j].0 - p).norm_squared());
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Mean distance from point `i` to its `k` nearest neighbours
+    fn mean_knn_distance(&self, i: usize, k: usize) -> f32 {
+        let mut distances = Vec::new();
+        let mut ring = 0;
+
+        // Points beyond ring `r` are at least `r` cells away, so the search
+        // stops once the k-th nearest distance is within that bound
+        loop {
+            // Isolated points would walk many empty rings, scanning every
+            // point is cheaper once a cube has more cells than are occupied
+            if ((2 * ring + 1) as usize).pow(3) > self.cells.len() {
+                let p = &self.points[i].0;
+
+                distances = (0..self.points.len())
+                    .filter(|j| *j != i)
+                    .map(|j| (self.points[j].0 - p).norm_squared())
+                    .collect();
+                distances.select_nth_unstable_by(k - 1, f32::total_cmp);
+
+                break;
+            }
+
+            self.ring(i, ring, |d| distances.push(d));
+
+            if distances.len() >= k {
+                distances.select_nth_unstable_by(k - 1, f32::total_cmp);
+
+                let bound = ring as f32 * self.cell;
+                if distances[k - 1] <= bound * bound {
+                    break;
+                }
+            }
+
+            ring += 1;
+        }
+
+        distances[..k].iter().map(|d| d.sqrt()).sum::<f32>() / k as f32
+    }
+
+    /// Number of other points within `radius` of point `i`, the cells must be
+    /// at least `radius` wide
+    fn count_within(&self, i: usize, radius: f32) -> usize {
+        let mut count = 0;
+
+        self.ring(i, 0, |d| count += (d <= radius * radius) as usize);
+        self.ring(i, 1, |d| count += (d <= radius * radius) as usize);
+
+        count
+    }
+}
+
+impl PointCloud {
+    /// Removes the points whose mean distance to their `k` nearest
+    /// neighbours is more than `std_ratio` standard deviations above the
+    /// mean, returns the number of removed points
+    pub fn remove_statistical_outliers(&mut self, k: usize, std_ratio: f32) -> usize {
+        let k = k.min(self.points.len().saturating_sub(1));
+
+        if k == 0 {
+            return 0;
+        }
+
+        // Cells sized to hold about k points on average, refined on the
+        // occupied cells since outliers inflate the bounding box
+        let size = self.bbox().size();
+        let volume = size.map(|s| s.max(f32::EPSILON)).product();
+        let mut cell = (volume * k as f32 / self.points.len() as f32)
+            .cbrt()
+            .max(f32::EPSILON);
+
+        let mut hash = SpatialHash::new(&self.points, cell);
+
+        for _ in 0..16 {
+            if self.points.len() <= 2 * k * hash.cells.len() {
+                break;
+            }
+
+            cell /= 2.0;
+            hash = SpatialHash::new(&self.points, cell);
+        }
+
+        let distances = (0..self.points.len())
+            .into_par_iter()
+            .map(|i| hash.mean_knn_distance(i, k))
+            .collect::<Vec<f32>>();
+
+        let n = distances.len() as f32;
+        let mean = distances.iter().sum::<f32>() / n;
+        let std = (distances.iter().map(|d| (d - mean).powi(2)).sum::<f32>() / n).sqrt();
+        let threshold = mean + std_ratio * std;
+
+        let keep = distances
+            .iter()
+            .map(|d| *d <= threshold)
+            .collect::<Vec<bool>>();
+
+        self.retain(&keep)
+    }
+
+    /// Removes the points with fewer than `min_neighbors` other points within
+    /// `radius`, returns the number of removed points
+    pub fn remove_radius_outliers(&mut self, radius: f32, min_neighbors: usize) -> usize {
+        assert!(radius > 0.0, "Outlier radius must be positive");
+
+        let hash = SpatialHash::new(&self.points, radius);
+
+        let keep = (0..self.points.len())
+            .into_par_iter()
+            .map(|i| hash.count_within(i, radius) >= min_neighbors)
+            .collect::<Vec<bool>>();
+
+        self.retain(&keep)
+    }
+
+    /// Keeps the points flagged in `keep`, returns the number of removed
+    /// points
+    pub fn retain(&mut self, keep: &[bool]) -> usize {
+        let len = self.points.len();
+
+        let mut i = 0;
+        self.points.retain(|_| {
+            i += 1;
+            keep[i - 1]
+        });
+
+        if !self.sensors.is_empty() {
+            let mut i = 0;
+            self.sensors.retain(|_| {
+                i += 1;
+                keep[i - 1]
+            });
+        }
+
+        len - self.points.len()
+    }
+}
@@ -1,42 +1,200 @@
 use ahash::AHashMap;
 use indicatif::ProgressBar;
-use nalgebra::Vector3;
+use nalgebra::{Matrix3, Matrix4, Point3, Vector3};
+
+use crate::{bbox::Bbox, formats::voxels::Voxel, lattice::Lattice};
+
+pub use carve::{Carving, OccupancyGrid};
+pub use filter::PointFilter;
+
+mod carve;
+mod filter;
 
 pub struct PointCloud {
     points: Vec<(Vector3<f32>, [u8; 4])>,
+    /// Position of the sensor that captured every point, empty when unknown
+    sensors: Vec<Vector3<f32>>,
+    /// Global position of the local origin the points are relative to
+    offset: Vector3<f64>,
+}
+
+/// Origin subtracted from large coordinates before they are stored in single
+/// precision
+#[derive(Clone, Copy, Debug, Default)]
+pub enum LocalOrigin {
+    /// Keep the coordinates as they are
+    #[default]
+    None,
+    /// Bounding box centre, rounded so that it lands on a multiple of `step`,
+    /// the voxel size, once `linear` maps it to the output frame. The local
+    /// lattice then stays aligned with the global one
+    Auto {
+        step: Vector3<f64>,
+        linear: Matrix3<f64>,
+    },
+    Given(Vector3<f64>),
+}
+
+impl LocalOrigin {
+    /// Parses `auto`, rounded to multiples of `step` in the output frame
+    /// `linear` maps to, or comma separated x,y,z coordinates
+    pub fn parse(value: &str, step: Vector3<f64>, linear: Matrix3<f64>) -> Self {
+        if value == "auto" {
+            return LocalOrigin::Auto { step, linear };
+        }
+
+        let values = value
+            .split(',')
+            .map(|v| v.trim().parse::<f64>().expect("Invalid local origin"))
+            .collect::<Vec<f64>>();
+
+        match values.as_slice() {
+            [x, y, z] => LocalOrigin::Given(Vector3::new(*x, *y, *z)),
+            _ => panic!("Local origin expects 'auto' or 3 comma separated values"),
+        }
+    }
+
+    /// Origin for a set of global points
+    pub fn resolve<'a>(&self, points: impl Iterator<Item = &'a Vector3<f64>>) -> Vector3<f64> {
+        match self {
+            LocalOrigin::None => Vector3::zeros(),
+            LocalOrigin::Given(origin) => *origin,
+            LocalOrigin::Auto { step, linear } => {
+                let mut min = Vector3::repeat(f64::MAX);
+                let mut max = Vector3::repeat(f64::MIN);
+
+                for p in points {
+                    min = min.inf(p);
+                    max = max.sup(p);
+                }
+
+                let inverse = linear
+                    .try_inverse()
+                    .expect("Source transform isn't invertible");
+
+                match min.x <= max.x {
+                    true => {
+                        inverse
+                            * (linear * (min + max) / 2.0)
+                                .component_div(step)
+                                .map(f64::round)
+                                .component_mul(step)
+                    }
+                    false => Vector3::zeros(),
+                }
+            }
+        }
+    }
 }
 
 impl PointCloud {
     pub fn new(points: Vec<(Vector3<f32>, [u8; 4])>) -> Self {
-        Self { points }
+        Self {
+            points,
+            sensors: Vec::new(),
+            offset: Vector3::zeros(),
+        }
+    }
+
+    /// Sets the global position of the local origin the points are relative to
+    pub fn with_offset(mut self, offset: Vector3<f64>) -> Self {
+        self.offset = offset;
+        self
     }
 
-    pub fn voxelize(&self, resolution: f32, bar: &ProgressBar) -> Vec<(Vector3<i32>, [u8; 4])> {
-        let mut set: AHashMap<Vector3<i32>, [u8; 4]> = AHashMap::new();
+    /// Builds a point cloud from double precision coordinates, stored relative
+    /// to a local origin
+    pub fn from_global(points: Vec<(Vector3<f64>, [u8; 4])>, origin: &LocalOrigin) -> Self {
+        let offset = origin.resolve(points.iter().map(|(p, _)| p));
+
+        let points = points
+            .into_iter()
+            .map(|(p, color)| ((p - offset).cast::<f32>(), color))
+            .collect();
+
+        Self {
+            points,
+            sensors: Vec::new(),
+            offset,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.points.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    /// Sets the global sensor position of every point
+    pub fn set_sensors(&mut self, sensors: Vec<Vector3<f64>>) {
+        assert_eq!(sensors.len(), self.points.len());
+
+        self.sensors = sensors
+            .into_iter()
+            .map(|s| (s - self.offset).cast::<f32>())
+            .collect();
+    }
+
+    /// Sets a global sensor position shared by every point
+    pub fn set_sensor_origin(&mut self, origin: Vector3<f64>) {
+        self.set_sensors(vec![origin; self.points.len()]);
+    }
+
+    pub fn has_sensors(&self) -> bool {
+        !self.sensors.is_empty()
+    }
+
+    /// Global position of the local origin
+    pub fn offset(&self) -> Vector3<f64> {
+        self.offset
+    }
+
+    pub fn bbox(&self) -> Bbox {
+        Bbox::from_pcl(self.points.iter().map(|(p, _)| p))
+    }
+
+    /// Voxelizes the points, dropping voxels with fewer than `min_points`
+    /// points. Returns the voxels and the number of points in each of them
+    pub fn voxelize(
+        &self,
+        lattice: &Lattice,
+        min_points: usize,
+        bar: &ProgressBar,
+    ) -> (Vec<Voxel>, Vec<u32>) {
+        let mut set: AHashMap<Vector3<i32>, ([u8; 4], u32)> = AHashMap::new();
 
         bar.set_length(self.points.len() as u64);
 
         for (point, color) in &self.points {
-            let pos = Vector3::new(
-                (point.x / resolution).round() as i32,
-                (point.y / resolution).round() as i32,
-                (point.z / resolution).round() as i32,
-            );
+            let pos = lattice.voxel(point);
 
             bar.inc(1);
 
-            set.insert(pos, *color);
+            let entry = set.entry(pos).or_insert((*color, 0));
+            entry.0 = *color;
+            entry.1 += 1;
         }
 
-        set.into_iter().collect::<Vec<(Vector3<i32>, [u8; 4])>>()
+        set.into_iter()
+            .filter(|(_, (_, count))| *count as usize >= min_points)
+            .map(|(pos, (color, count))| ((pos, color), count))
+            .unzip()
     }
 
-    pub fn rotate(&mut self, rotation: Vector3<f32>) {
-        let rotation_matrix =
-            nalgebra::Rotation3::from_euler_angles(rotation.x, rotation.y, rotation.z);
-
+    /// Applies an affine transform to every point. The points are transformed
+    /// in the local frame, the offset only goes through the linear part so
+    /// that offset and points still add up to the transformed global points
+    pub fn transform(&mut self, matrix: &Matrix4<f32>) {
         for (point, _) in &mut self.points {
-            *point = rotation_matrix * *point;
+            *point = matrix.transform_point(&Point3::from(*point)).coords;
         }
+
+        for sensor in &mut self.sensors {
+            *sensor = matrix.transform_point(&Point3::from(*sensor)).coords;
+        }
+
+        self.offset = matrix.fixed_view::<3, 3>(0, 0).into_owned().cast::<f64>() * self.offset;
     }
 }
@@ -0,0 +1,272 @@
+use indicatif::ProgressBar;
+use nalgebra::{Matrix4, Vector2, Vector3};
+
+use crate::{
+    bbox::Bbox,
+    formats::voxels::Voxel,
+    lattice::{Fill, Lattice, Sampled},
+};
+
+/// How the children of a combination are merged
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Operation {
+    Union,
+    /// The first child minus the others
+    Subtract,
+    Intersect,
+}
+
+/// Signed distance function, negative inside
+#[derive(Clone, Debug)]
+pub enum Sdf {
+    Sphere {
+        center: Vector3<f32>,
+        radius: f32,
+    },
+    Box {
+        center: Vector3<f32>,
+        /// Half size along each axis
+        extent: Vector3<f32>,
+    },
+    Capsule {
+        a: Vector3<f32>,
+        b: Vector3<f32>,
+        radius: f32,
+    },
+    /// Ring around the z axis
+    Torus {
+        center: Vector3<f32>,
+        major: f32,
+        minor: f32,
+    },
+    /// Capped cylinder between the centres of its caps
+    Cylinder {
+        a: Vector3<f32>,
+        b: Vector3<f32>,
+        radius: f32,
+    },
+    /// Boolean combination, blended over `smoothness` when it isn't 0
+    Combine {
+        operation: Operation,
+        smoothness: f32,
+        children: Vec<Sdf>,
+    },
+    Color {
+        color: Vector3<f32>,
+        child: Box<Sdf>,
+    },
+}
+
+impl Sdf {
+    /// Signed distance and colour at a position, shapes without a colour
+    /// of their own get `color`
+    pub fn evaluate(&self, p: &Vector3<f32>, color: Vector3<f32>) -> (f32, Vector3<f32>) {
+        match self {
+            Sdf::Sphere { center, radius } => ((p - center).norm() - radius, color),
+            Sdf::Box { center, extent } => {
+                let q = (p - center).abs() - extent;
+                let distance = q.map(|v| v.max(0.0)).norm() + q.max().min(0.0);
+
+                (distance, color)
+            }
+            Sdf::Capsule { a, b, radius } => {
+                let (pa, ba) = (p - a, b - a);
+                let h = (pa.dot(&ba) / ba.norm_squared().max(f32::EPSILON)).clamp(0.0, 1.0);
+
+                ((pa - ba * h).norm() - radius, color)
+            }
+            Sdf::Torus {
+                center,
+                major,
+                minor,
+            } => {
+                let p = p - center;
+                let q = Vector2::new(p.xy().norm() - major, p.z);
+
+                (q.norm() - minor, color)
+            }
+            Sdf::Cylinder { a, b, radius } => {
+                let (pa, ba) = (p - a, b - a);
+                let length = ba.norm().max(f32::EPSILON);
+
+                // Distances to the side and to the caps
+                let along = pa.dot(&ba) / length;
+                let d = Vector2::new(
+                    (pa - ba * (along / length)).norm() - radius,
+                    (along - length / 2.0).abs() - length / 2.0,
+                );
+
+                let distance = d.map(|v| v.max(0.0)).norm() + d.max().min(0.0);
+
+                (distance, color)
+            }
+            Sdf::Combine {
+                operation,
+                smoothness,
+                children,
+            } => {
+                let mut children = children.iter().map(|child| child.evaluate(p, color));
+
+                let first = children.next().unwrap_or((f32::MAX, color));
+
+                children.fold(first, |a, b| combine(*operation, *smoothness, a, b))
+            }
+            Sdf::Color { color, child } => child.evaluate(p, *color),
+        }
+    }
+
+    /// Box containing the inside
+    pub fn bbox(&self) -> Bbox {
+        let segment = |a: &Vector3<f32>, b: &Vector3<f32>, radius: f32| {
+            Bbox::new(a.inf(b).add_scalar(-radius), a.sup(b).add_scalar(radius))
+        };
+
+        match self {
+            Sdf::Sphere { center, radius } => {
+                Bbox::new(center.add_scalar(-radius), center.add_scalar(*radius))
+            }
+            Sdf::Box { center, extent } => Bbox::new(center - extent, center + extent),
+            Sdf::Capsule { a, b, radius } | Sdf::Cylinder { a, b, radius } => {
+                segment(a, b, *radius)
+            }
+            Sdf::Torus {
+                center,
+                major,
+                minor,
+            } => {
+                let extent = Vector3::new(major + minor, major + minor, *minor);
+                Bbox::new(center - extent, center + extent)
+            }
+            Sdf::Combine {
+                operation,
+                smoothness,
+                children,
+            } => {
+                let mut bboxes = children.iter().map(|child| child.bbox());
+                let first = bboxes
+                    .next()
+                    .unwrap_or(Bbox::new(Vector3::zeros(), Vector3::zeros()));
+
+                match operation {
+                    // Blends can bulge out of the children
+                    Operation::Union => {
+                        let bbox = bboxes.fold(first, |a, b| a.union(&b));
+                        Bbox::new(
+                            bbox.min.add_scalar(-smoothness),
+                            bbox.max.add_scalar(*smoothness),
+                        )
+                    }
+                    Operation::Subtract => first,
+                    Operation::Intersect => bboxes.fold(first, |a, b| {
+                        let min = a.min.sup(&b.min);
+                        // Disjoint children leave an empty box
+                        Bbox::new(min, a.max.inf(&b.max).sup(&min))
+                    }),
+                }
+            }
+            Sdf::Color { child, .. } => child.bbox(),
+        }
+    }
+}
+
+/// Merges two distances and colours, smoothly within `k` with the
+/// polynomial blends of Inigo Quilez
+fn combine(
+    operation: Operation,
+    k: f32,
+    (a, color_a): (f32, Vector3<f32>),
+    (b, color_b): (f32, Vector3<f32>),
+) -> (f32, Vector3<f32>) {
+    if k <= 0.0 {
+        return match operation {
+            Operation::Union if b < a => (b, color_b),
+            Operation::Union => (a, color_a),
+            Operation::Subtract => (a.max(-b), color_a),
+            Operation::Intersect if b > a => (b, color_b),
+            Operation::Intersect => (a, color_a),
+        };
+    }
+
+    // `h` is the blend weight of `a`, or of `-b` when subtracting
+    match operation {
+        Operation::Union => {
+            let h = (0.5 + 0.5 * (b - a) / k).clamp(0.0, 1.0);
+            (
+                b + (a - b) * h - k * h * (1.0 - h),
+                color_b.lerp(&color_a, h),
+            )
+        }
+        Operation::Subtract => {
+            let h = (0.5 - 0.5 * (b + a) / k).clamp(0.0, 1.0);
+            (a + (-b - a) * h + k * h * (1.0 - h), color_a)
+        }
+        Operation::Intersect => {
+            let h = (0.5 - 0.5 * (b - a) / k).clamp(0.0, 1.0);
+            (
+                b + (a - b) * h + k * h * (1.0 - h),
+                color_b.lerp(&color_a, h),
+            )
+        }
+    }
+}
+
+/// Signed distance scene, the union of its top level shapes
+pub struct Scene {
+    root: Sdf,
+    fill: Fill,
+}
+
+impl Scene {
+    pub fn new(root: Sdf) -> Self {
+        Self {
+            root,
+            fill: Fill::default(),
+        }
+    }
+
+    /// Which voxels inside the scene are kept
+    pub fn with_fill(mut self, fill: Fill) -> Self {
+        self.fill = fill;
+        self
+    }
+}
+
+impl Sampled for Scene {
+    /// Bounding box of the inside
+    fn bbox(&self) -> Bbox {
+        self.root.bbox()
+    }
+
+    /// Voxels whose centre is inside the scene
+    fn voxelize(
+        &self,
+        lattice: &Lattice,
+        transform: &Matrix4<f32>,
+        bar: &ProgressBar,
+    ) -> Vec<Voxel> {
+        let inverse = transform
+            .try_inverse()
+            .expect("Scene transform isn't invertible");
+
+        let inside =
+            |position: &Vector3<f32>| self.root.evaluate(position, Vector3::zeros()).0 <= 0.0;
+
+        let sample = |voxel: &Vector3<i32>, position: &Vector3<f32>| {
+            let (distance, color) = self.root.evaluate(position, Vector3::repeat(255.0));
+
+            if distance > 0.0 {
+                return None;
+            }
+
+            if self.fill == Fill::Surface && !lattice.exposed(voxel, &inverse, inside) {
+                return None;
+            }
+
+            let color = color.map(|c| c.clamp(0.0, 255.0) as u8);
+
+            Some([color.x, color.y, color.z, 255])
+        };
+
+        lattice.sample(&self.bbox().transform(transform), transform, sample, bar)
+    }
+}
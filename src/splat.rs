@@ -0,0 +1,179 @@
+use ahash::AHashMap;
+use clap::ValueEnum;
+use indicatif::ProgressBar;
+use nalgebra::{Matrix3, Matrix4, Point3, Vector3};
+
+use crate::{bbox::Bbox, formats::voxels::Voxel, lattice::Lattice, pointcloud::PointCloud};
+
+/// How Gaussian splats are turned into voxels
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SplatMode {
+    /// Every voxel inside the ellipsoid of a splat, coloured by the splats
+    /// covering it weighted by their opacity and density
+    #[default]
+    Ellipsoids,
+    /// The voxel of the centre of every splat, as a point cloud
+    Centers,
+}
+
+/// 3D Gaussian
+#[derive(Clone, Copy, Debug)]
+pub struct Splat {
+    pub center: Vector3<f32>,
+    /// Principal axes scaled by the standard deviations, the Gaussian is
+    /// `center + axes * u` with `u` standard normal
+    pub axes: Matrix3<f32>,
+    pub opacity: f32,
+    pub color: [u8; 3],
+}
+
+pub struct Splats {
+    splats: Vec<Splat>,
+    /// Global position of the local origin the splats are relative to
+    offset: Vector3<f64>,
+}
+
+impl Splats {
+    pub fn new(splats: Vec<Splat>, offset: Vector3<f64>) -> Self {
+        Self { splats, offset }
+    }
+
+    /// Global position of the local origin
+    pub fn offset(&self) -> Vector3<f64> {
+        self.offset
+    }
+
+    /// Bounding box of the splat centres, the ellipsoids are clamped to it so
+    /// a few huge splats don't blow up the scene
+    pub fn bbox(&self) -> Bbox {
+        Bbox::from_pcl(self.splats.iter().map(|splat| &splat.center))
+    }
+
+    /// Applies an affine transform to the centres and axes of the splats, the
+    /// offset only goes through the linear part
+    pub fn transform(&mut self, matrix: &Matrix4<f32>) {
+        let linear = matrix.fixed_view::<3, 3>(0, 0).into_owned();
+
+        for splat in &mut self.splats {
+            splat.center = matrix.transform_point(&Point3::from(splat.center)).coords;
+            splat.axes = linear * splat.axes;
+        }
+
+        self.offset = linear.cast::<f64>() * self.offset;
+    }
+
+    /// Point cloud of the splat centres
+    pub fn centers(&self) -> PointCloud {
+        let points = self
+            .splats
+            .iter()
+            .map(|splat| {
+                let [r, g, b] = splat.color;
+                (splat.center, [r, g, b, 255])
+            })
+            .collect();
+
+        PointCloud::new(points).with_offset(self.offset)
+    }
+
+    /// Rasterizes the ellipsoids cut at `sigma` standard deviations and
+    /// clamped to the bounding box, every splat covers at least the voxel of
+    /// its centre. Splats spanning `max_size` voxels or more along an axis
+    /// are skipped. Returns the voxels, the number of splats covering each of
+    /// them and the number of skipped splats
+    pub fn voxelize(
+        &self,
+        lattice: &Lattice,
+        sigma: f32,
+        max_size: u32,
+        bar: &ProgressBar,
+    ) -> (Vec<Voxel>, Vec<u32>, usize) {
+        // Weighted colour sum, weight sum and splat count of every voxel
+        let mut grid: AHashMap<Vector3<i32>, (Vector3<f32>, f32, u32)> = AHashMap::new();
+
+        let bbox = self.bbox();
+        let (scene_min, scene_max) = (lattice.voxel(&bbox.min), lattice.voxel(&bbox.max));
+
+        let mut skipped = 0;
+
+        bar.set_length(self.splats.len() as u64);
+
+        for splat in &self.splats {
+            let color = Vector3::from(splat.color.map(|c| c as f32));
+
+            let mut add = |voxel: Vector3<i32>, weight: f32| {
+                let entry = grid.entry(voxel).or_insert((Vector3::zeros(), 0.0, 0));
+                entry.0 += color * weight;
+                entry.1 += weight;
+                entry.2 += 1;
+            };
+
+            let extent = extent(splat, sigma);
+
+            // Compared as floats, the voxels of huge splats overflow i32
+            let size = (2.0 * extent).component_div(&lattice.resolution).max();
+
+            if !splat.axes.iter().all(|v| v.is_finite()) || size >= max_size as f32 {
+                skipped += 1;
+                bar.inc(1);
+                continue;
+            }
+
+            let center = lattice.voxel(&splat.center);
+
+            let inverse = match splat.axes.try_inverse() {
+                Some(inverse) => inverse,
+                None => {
+                    add(center, splat.opacity);
+                    bar.inc(1);
+                    continue;
+                }
+            };
+
+            let min = lattice.voxel(&(splat.center - extent)).sup(&scene_min);
+            let max = lattice.voxel(&(splat.center + extent)).inf(&scene_max);
+
+            let mut covered = false;
+
+            for x in min.x..=max.x {
+                for y in min.y..=max.y {
+                    for z in min.z..=max.z {
+                        let voxel = Vector3::new(x, y, z);
+
+                        // Squared Mahalanobis distance of the voxel centre
+                        let distance =
+                            (inverse * (lattice.center(&voxel) - splat.center)).norm_squared();
+
+                        if distance <= sigma * sigma {
+                            add(voxel, splat.opacity * (-0.5 * distance).exp());
+                            covered |= voxel == center;
+                        }
+                    }
+                }
+            }
+
+            if !covered {
+                add(center, splat.opacity);
+            }
+
+            bar.inc(1);
+        }
+
+        let (voxels, counts) = grid
+            .into_iter()
+            .map(|(voxel, (color, weight, count))| {
+                let color = (color / weight.max(f32::EPSILON)).map(|c| c.clamp(0.0, 255.0) as u8);
+
+                ((voxel, [color.x, color.y, color.z, 255]), count)
+            })
+            .unzip();
+
+        (voxels, counts, skipped)
+    }
+}
+
+/// Half size along each world axis of the ellipsoid of a splat cut at
+/// `sigma` standard deviations
+fn extent(splat: &Splat, sigma: f32) -> Vector3<f32> {
+    Vector3::from_fn(|i, _| splat.axes.row(i).norm() * sigma)
+}
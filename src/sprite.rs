@@ -0,0 +1,126 @@
+use image::RgbaImage;
+use indicatif::ProgressBar;
+use nalgebra::{Matrix4, Vector3};
+
+use crate::{
+    bbox::Bbox,
+    formats::voxels::Voxel,
+    lattice::{Lattice, Sampled},
+};
+
+/// Pixels with a lower alpha are transparent
+const OPAQUE: u8 = 128;
+
+/// Pixel-art sprite extruded along its depth. Sprite space has x along the
+/// columns, z up the rows and y into the sprite, the front view looking
+/// along +y. A pixel spans `pixel_size` along every axis
+pub struct Sprite {
+    front: RgbaImage,
+    /// Seen from behind, so mirrored left to right
+    back: Option<RgbaImage>,
+    /// Seen from the +x side, its columns going from front to back
+    side: Option<RgbaImage>,
+    depth: u32,
+    pixel_size: f32,
+}
+
+impl Sprite {
+    /// Sprite extruded `depth` pixels, defaulting to the width of the side
+    /// silhouette or 1
+    pub fn new(
+        front: RgbaImage,
+        back: Option<RgbaImage>,
+        side: Option<RgbaImage>,
+        depth: Option<u32>,
+        pixel_size: f32,
+    ) -> Self {
+        if let Some(back) = &back {
+            assert!(
+                back.dimensions() == front.dimensions(),
+                "Back sprite and front sprite have different sizes"
+            );
+        }
+
+        if let Some(side) = &side {
+            assert!(
+                side.height() == front.height(),
+                "Side sprite and front sprite have different heights"
+            );
+        }
+
+        let depth = depth.unwrap_or(side.as_ref().map_or(1, |side| side.width()));
+
+        Self {
+            front,
+            back,
+            side,
+            depth,
+            pixel_size,
+        }
+    }
+}
+
+impl Sampled for Sprite {
+    fn bbox(&self) -> Bbox {
+        let size = Vector3::new(
+            self.front.width() as f32,
+            self.depth as f32,
+            self.front.height() as f32,
+        ) * self.pixel_size;
+
+        Bbox::new(Vector3::zeros(), size)
+    }
+
+    /// Voxels whose centre is opaque in every given view, coloured by the
+    /// front sprite on the front half and the back sprite on the back half
+    fn voxelize(
+        &self,
+        lattice: &Lattice,
+        transform: &Matrix4<f32>,
+        bar: &ProgressBar,
+    ) -> Vec<Voxel> {
+        let (width, height) = self.front.dimensions();
+
+        let sample = |_: &Vector3<i32>, position: &Vector3<f32>| {
+            let pixel = (position / self.pixel_size).map(|v| v.floor());
+
+            if pixel.iter().any(|v| *v < 0.0)
+                || pixel.x >= width as f32
+                || pixel.y >= self.depth as f32
+                || pixel.z >= height as f32
+            {
+                return None;
+            }
+
+            let (x, y) = (pixel.x as u32, pixel.y as u32);
+            let row = height - 1 - pixel.z as u32;
+
+            let front = self.front.get_pixel(x, row).0;
+            let back = self
+                .back
+                .as_ref()
+                .map(|back| back.get_pixel(width - 1 - x, row).0);
+            let side = self.side.as_ref().map(|side| match y < side.width() {
+                true => side.get_pixel(y, row).0,
+                false => [0; 4],
+            });
+
+            if [Some(front), back, side]
+                .iter()
+                .flatten()
+                .any(|color| color[3] < OPAQUE)
+            {
+                return None;
+            }
+
+            let [r, g, b, _] = match back {
+                Some(back) if 2 * y >= self.depth => back,
+                _ => front,
+            };
+
+            Some([r, g, b, 255])
+        };
+
+        lattice.sample(&self.bbox().transform(transform), transform, sample, bar)
+    }
+}
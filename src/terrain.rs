@@ -0,0 +1,116 @@
+use image::RgbaImage;
+use indicatif::ProgressBar;
+use nalgebra::{Matrix4, Vector3};
+
+use crate::{
+    bbox::Bbox,
+    formats::voxels::Voxel,
+    lattice::{Fill, Lattice, Sampled},
+    mesh::sample_texture,
+};
+
+/// Grid of heights, pixel `(i, j)` spans `[i, i + 1) * pixel_size` along x
+/// and the first row is at the far end of y, as an image seen from above
+pub struct Heightmap {
+    width: u32,
+    height: u32,
+    heights: Vec<f32>,
+    pixel_size: f32,
+    colors: Option<RgbaImage>,
+    fill: Fill,
+}
+
+impl Heightmap {
+    pub fn new(width: u32, height: u32, heights: Vec<f32>, pixel_size: f32) -> Self {
+        Self {
+            width,
+            height,
+            heights,
+            pixel_size,
+            colors: None,
+            fill: Fill::default(),
+        }
+    }
+
+    /// Colour map stretched over the whole heightmap
+    pub fn with_colors(mut self, colors: RgbaImage) -> Self {
+        self.colors = Some(colors);
+        self
+    }
+
+    /// Which voxels inside the terrain are kept
+    pub fn with_fill(mut self, fill: Fill) -> Self {
+        self.fill = fill;
+        self
+    }
+
+    /// Pixel under a heightmap space position
+    fn pixel(&self, position: &Vector3<f32>) -> Option<(u32, u32)> {
+        let x = (position.x / self.pixel_size).floor();
+        let y = self.height as f32 - (position.y / self.pixel_size).floor() - 1.0;
+
+        match x >= 0.0 && y >= 0.0 && x < self.width as f32 && y < self.height as f32 {
+            true => Some((x as u32, y as u32)),
+            false => None,
+        }
+    }
+
+    fn ground(&self, (x, y): (u32, u32)) -> f32 {
+        self.heights[(y * self.width + x) as usize]
+    }
+}
+
+impl Sampled for Heightmap {
+    fn bbox(&self) -> Bbox {
+        let size = Vector3::new(
+            self.width as f32 * self.pixel_size,
+            self.height as f32 * self.pixel_size,
+            self.heights.iter().copied().fold(0.0, f32::max),
+        );
+
+        Bbox::new(Vector3::zeros(), size)
+    }
+
+    /// Voxels whose centre is between the ground and the height of the pixel
+    /// under it. Surfaces are the voxels next to the air above and around
+    /// the terrain, the top layer and the sides of cliffs and of the map
+    fn voxelize(
+        &self,
+        lattice: &Lattice,
+        transform: &Matrix4<f32>,
+        bar: &ProgressBar,
+    ) -> Vec<Voxel> {
+        let inverse = transform
+            .try_inverse()
+            .expect("Heightmap transform isn't invertible");
+
+        // Outside the map is air, so edge columns have exposed sides
+        let inside = |position: &Vector3<f32>| match self.pixel(position) {
+            Some(pixel) => position.z <= self.ground(pixel),
+            None => false,
+        };
+
+        let sample = |voxel: &Vector3<i32>, position: &Vector3<f32>| {
+            let pixel = self.pixel(position)?;
+
+            if position.z < 0.0 || position.z > self.ground(pixel) {
+                return None;
+            }
+
+            if self.fill == Fill::Surface && !lattice.exposed(voxel, &inverse, inside) {
+                return None;
+            }
+
+            Some(match &self.colors {
+                Some(colors) => {
+                    let u = position.x / (self.width as f32 * self.pixel_size);
+                    let v = 1.0 - position.y / (self.height as f32 * self.pixel_size);
+                    sample_texture(colors, u.clamp(0.0, 1.0), v.clamp(0.0, 1.0))
+                }
+                None => [255u8; 4],
+            })
+        };
+
+        lattice.sample(&self.bbox().transform(transform), transform, sample, bar)
+    }
+}
@@ -0,0 +1,122 @@
+use clap::ValueEnum;
+use nalgebra::{Matrix4, Rotation3, Unit, UnitQuaternion, Vector3};
+
+/// Affine transform applied to the loaded geometry before voxelization.
+///
+/// The components are composed in a fixed order: the matrix is applied
+/// first, then the scale, the Euler rotation, the quaternion rotation, the
+/// axis-angle rotation and finally the translation
+#[derive(Clone, Debug, Default)]
+pub struct Transform {
+    pub matrix: Option<Matrix4<f32>>,
+    pub scale: Option<Vector3<f32>>,
+    /// Rotations around the x, y and z axes in radians
+    pub euler: Option<Vector3<f32>>,
+    pub quaternion: Option<UnitQuaternion<f32>>,
+    /// Rotation axis and angle in radians
+    pub axis_angle: Option<(Unit<Vector3<f32>>, f32)>,
+    pub translation: Option<Vector3<f32>>,
+}
+
+impl Transform {
+    pub fn matrix(&self) -> Matrix4<f32> {
+        let mut matrix = self.matrix.unwrap_or_else(Matrix4::identity);
+
+        if let Some(scale) = self.scale {
+            matrix = Matrix4::new_nonuniform_scaling(&scale) * matrix;
+        }
+
+        if let Some(euler) = self.euler {
+            matrix =
+                Rotation3::from_euler_angles(euler.x, euler.y, euler.z).to_homogeneous() * matrix;
+        }
+
+        if let Some(quaternion) = self.quaternion {
+            matrix = quaternion.to_homogeneous() * matrix;
+        }
+
+        if let Some((axis, angle)) = self.axis_angle {
+            matrix = Rotation3::from_axis_angle(&axis, angle).to_homogeneous() * matrix;
+        }
+
+        if let Some(translation) = self.translation {
+            matrix = Matrix4::new_translation(&translation) * matrix;
+        }
+
+        matrix
+    }
+}
+
+/// Axis conventions of a coordinate system
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Frame {
+    /// Right handed, y up, as in glTF
+    YUp,
+    /// Right handed, z up, as in Blender, LAS and most scanners
+    ZUp,
+    /// Left handed, y up, as in Unity and Minecraft-like engines
+    YUpLeft,
+    /// Left handed, z up, as in Unreal
+    ZUpLeft,
+}
+
+impl Frame {
+    /// Matrix taking coordinates in this frame to the glTF frame
+    fn to_y_up(self) -> Matrix4<f32> {
+        match self {
+            Frame::YUp => Matrix4::identity(),
+            Frame::ZUp => Matrix4::new(
+                1.0, 0.0, 0.0, 0.0, //
+                0.0, 0.0, 1.0, 0.0, //
+                0.0, -1.0, 0.0, 0.0, //
+                0.0, 0.0, 0.0, 1.0,
+            ),
+            Frame::YUpLeft => Matrix4::new_nonuniform_scaling(&Vector3::new(1.0, 1.0, -1.0)),
+            Frame::ZUpLeft => Matrix4::new(
+                1.0, 0.0, 0.0, 0.0, //
+                0.0, 0.0, 1.0, 0.0, //
+                0.0, 1.0, 0.0, 0.0, //
+                0.0, 0.0, 0.0, 1.0,
+            ),
+        }
+    }
+
+    /// Index of the up axis
+    pub fn up(self) -> usize {
+        match self {
+            Frame::YUp | Frame::YUpLeft => 1,
+            Frame::ZUp | Frame::ZUpLeft => 2,
+        }
+    }
+}
+
+/// Length units of the input coordinates
+#[derive(ValueEnum, Clone, Copy, Debug, Default)]
+pub enum Units {
+    #[default]
+    M,
+    Cm,
+    Mm,
+    Inch,
+    Ft,
+}
+
+impl Units {
+    pub fn meters(self) -> f32 {
+        match self {
+            Units::M => 1.0,
+            Units::Cm => 0.01,
+            Units::Mm => 0.001,
+            Units::Inch => 0.0254,
+            Units::Ft => 0.3048,
+        }
+    }
+}
+
+/// Matrix converting coordinates from one frame and unit to another frame in
+/// meters
+pub fn conversion(from: Frame, to: Frame, units: Units) -> Matrix4<f32> {
+    // Frame matrices are permutations and reflections, their inverse is
+    // their transpose
+    to.to_y_up().transpose() * from.to_y_up() * Matrix4::new_scaling(units.meters())
+}
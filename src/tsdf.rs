@@ -0,0 +1,218 @@
+use ahash::{AHashMap, AHashSet};
+use image::RgbaImage;
+use nalgebra::{Matrix4, Point3, Vector3};
+use rayon::prelude::*;
+
+use crate::{
+    bbox::Bbox,
+    formats::{
+        rgbd::{DepthImage, Intrinsics},
+        voxels::Voxel,
+    },
+    lattice::Lattice,
+};
+
+/// Fused observations of a voxel
+#[derive(Clone, Copy, Debug)]
+struct Cell {
+    /// Weighted mean signed distance to the surface divided by the
+    /// truncation, positive in front of it
+    distance: f32,
+    weight: f32,
+    /// Weighted mean colour, from the frames that have a colour image
+    color: Vector3<f32>,
+    color_weight: f32,
+}
+
+/// Sparse truncated signed distance grid fused from depth maps
+pub struct Tsdf {
+    lattice: Lattice,
+    /// Distance to the surface beyond which observations are cut, in world
+    /// units
+    truncation: f32,
+    /// Ignore depths beyond this, in meters
+    max_depth: Option<f32>,
+    cells: AHashMap<Vector3<i32>, Cell>,
+}
+
+impl Tsdf {
+    pub fn new(lattice: Lattice, truncation: f32, max_depth: Option<f32>) -> Self {
+        Self {
+            lattice,
+            truncation,
+            max_depth,
+            cells: AHashMap::new(),
+        }
+    }
+
+    /// Integrates a depth map seen from `pose`, the camera to world
+    /// transform. Only the voxels within the truncation band around the
+    /// measured surface are updated
+    pub fn integrate(
+        &mut self,
+        depth: &DepthImage,
+        color: Option<&RgbaImage>,
+        intrinsics: &Intrinsics,
+        pose: &Matrix4<f32>,
+    ) {
+        let inverse = pose
+            .try_inverse()
+            .expect("Rgbd camera pose isn't invertible");
+        let camera = pose.transform_point(&Point3::origin()).coords;
+
+        // Voxels of the truncation band along every pixel ray
+        let mut band = AHashSet::new();
+
+        for point in surface_points(depth, intrinsics, pose, self.max_depth) {
+            let direction = (point - camera).normalize();
+
+            let from = point - direction * self.truncation;
+            let to = point + direction * self.truncation;
+
+            self.lattice.traverse(&from, &to, |voxel| {
+                band.insert(voxel);
+            });
+            band.insert(self.lattice.voxel(&to));
+        }
+
+        let updates = band
+            .into_iter()
+            .collect::<Vec<_>>()
+            .into_par_iter()
+            .filter_map(|voxel| {
+                let center = self.lattice.center(&voxel);
+                let local = inverse.transform_point(&Point3::from(center)).coords;
+
+                if local.z <= 0.0 {
+                    return None;
+                }
+
+                let (u, v) = intrinsics.project(&local);
+                let (x, y) = (u.round(), v.round());
+
+                if x < 0.0 || y < 0.0 || x >= depth.width as f32 || y >= depth.height as f32 {
+                    return None;
+                }
+
+                let d = depth.get(x as u32, y as u32);
+
+                if d <= 0.0 || self.max_depth.is_some_and(|max| d > max) {
+                    return None;
+                }
+
+                // Distance along the pixel ray, measured in world units
+                let surface = pose
+                    .transform_point(&Point3::from(intrinsics.unproject(x, y, d)))
+                    .coords;
+                let distance = (surface - camera).norm() - (center - camera).norm();
+
+                if distance < -self.truncation {
+                    return None;
+                }
+
+                let color = color.map(|color| {
+                    let [r, g, b, _] = color.get_pixel(x as u32, y as u32).0;
+                    Vector3::new(r as f32, g as f32, b as f32)
+                });
+
+                Some((voxel, (distance / self.truncation).min(1.0), color))
+            })
+            .collect::<Vec<_>>();
+
+        for (voxel, distance, color) in updates {
+            let cell = self.cells.entry(voxel).or_insert(Cell {
+                distance: 0.0,
+                weight: 0.0,
+                color: Vector3::zeros(),
+                color_weight: 0.0,
+            });
+
+            cell.distance = (cell.distance * cell.weight + distance) / (cell.weight + 1.0);
+            cell.weight += 1.0;
+
+            if let Some(color) = color {
+                cell.color = (cell.color * cell.color_weight + color) / (cell.color_weight + 1.0);
+                cell.color_weight += 1.0;
+            }
+        }
+    }
+
+    /// Voxels on the zero crossings of the distance field. Of two neighbours
+    /// with opposite signs, the one closer to the surface is kept. Truncated
+    /// distances don't locate the surface and are skipped
+    pub fn extract(&self) -> Vec<Voxel> {
+        let mut surface = AHashSet::new();
+
+        for (voxel, cell) in &self.cells {
+            if cell.distance.abs() >= 1.0 {
+                continue;
+            }
+
+            for axis in 0..3 {
+                let mut neighbor = *voxel;
+                neighbor[axis] += 1;
+
+                let other = match self.cells.get(&neighbor) {
+                    Some(other) if other.distance.abs() < 1.0 => other,
+                    _ => continue,
+                };
+
+                if (cell.distance >= 0.0) == (other.distance >= 0.0) {
+                    continue;
+                }
+
+                surface.insert(match cell.distance.abs() <= other.distance.abs() {
+                    true => *voxel,
+                    false => neighbor,
+                });
+            }
+        }
+
+        surface
+            .into_iter()
+            .map(|voxel| {
+                let cell = &self.cells[&voxel];
+
+                let color = match cell.color_weight > 0.0 {
+                    true => cell.color.map(|c| c.clamp(0.0, 255.0) as u8),
+                    false => Vector3::repeat(255),
+                };
+
+                (voxel, [color.x, color.y, color.z, 255])
+            })
+            .collect()
+    }
+}
+
+/// Bounding box of the surface seen by a depth map from `pose`
+pub fn depth_bbox(
+    depth: &DepthImage,
+    intrinsics: &Intrinsics,
+    pose: &Matrix4<f32>,
+    max_depth: Option<f32>,
+) -> Option<Bbox> {
+    let points = surface_points(depth, intrinsics, pose, max_depth).collect::<Vec<_>>();
+
+    (!points.is_empty()).then(|| Bbox::from_pcl(&points))
+}
+
+/// World positions of the valid depths of a depth map
+fn surface_points<'a>(
+    depth: &'a DepthImage,
+    intrinsics: &'a Intrinsics,
+    pose: &'a Matrix4<f32>,
+    max_depth: Option<f32>,
+) -> impl Iterator<Item = Vector3<f32>> + 'a {
+    (0..depth.height)
+        .flat_map(move |y| (0..depth.width).map(move |x| (x, y)))
+        .filter_map(move |(x, y)| {
+            let d = depth.get(x, y);
+
+            if d <= 0.0 || max_depth.is_some_and(|max| d > max) {
+                return None;
+            }
+
+            let point = intrinsics.unproject(x as f32, y as f32, d);
+            Some(pose.transform_point(&Point3::from(point)).coords)
+        })
+}
@@ -0,0 +1,155 @@
+use indicatif::ProgressBar;
+use nalgebra::{Matrix4, Vector3};
+
+use crate::{
+    bbox::Bbox,
+    formats::voxels::Voxel,
+    lattice::{Lattice, Sampled},
+};
+
+/// Maps volume intensities to occupancy and grey levels
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Transfer {
+    /// Lowest occupied intensity, defaults to the bottom of the window, or
+    /// halfway through the intensity range
+    pub threshold: Option<f32>,
+    /// Width and level (centre) of the intensities spread from black to
+    /// white, defaults to the threshold up to the highest intensity
+    pub window: Option<(f32, f32)>,
+}
+
+/// Stack of image slices, pixel `(i, j)` of slice `k` spans `[i, i + 1) *
+/// pixel_size` along x, `[k, k + 1) * slice_spacing` along z, and the first
+/// row is at the far end of y
+pub struct Volume {
+    size: [u32; 3],
+    values: Vec<f32>,
+    pixel_size: f32,
+    slice_spacing: f32,
+    transfer: Transfer,
+}
+
+impl Volume {
+    pub fn new(size: [u32; 3], values: Vec<f32>, pixel_size: f32, slice_spacing: f32) -> Self {
+        Self {
+            size,
+            values,
+            pixel_size,
+            slice_spacing,
+            transfer: Transfer::default(),
+        }
+    }
+
+    /// How intensities map to occupancy and grey levels
+    pub fn with_transfer(mut self, transfer: Transfer) -> Self {
+        self.transfer = transfer;
+        self
+    }
+
+    pub fn slices(&self) -> u32 {
+        self.size[2]
+    }
+
+    /// Intensity at a volume space position
+    fn sample(&self, position: &Vector3<f32>) -> Option<f32> {
+        let [width, height, slices] = self.size.map(|s| s as f32);
+
+        let x = (position.x / self.pixel_size).floor();
+        let y = height - (position.y / self.pixel_size).floor() - 1.0;
+        let z = (position.z / self.slice_spacing).floor();
+
+        match x >= 0.0 && y >= 0.0 && z >= 0.0 && x < width && y < height && z < slices {
+            true => Some(self.values[((z * height + y) * width + x) as usize]),
+            false => None,
+        }
+    }
+
+    /// Highest intensity of the pixels whose centre is inside a volume
+    /// space box
+    fn max(&self, bbox: &Bbox) -> Option<f32> {
+        let [width, height, slices] = self.size.map(|s| s as i64);
+
+        // Pixels whose centre is inside the box along one axis
+        let range = |min: f32, max: f32, step: f32, size: i64| {
+            let first = ((min / step - 0.5).ceil() as i64).max(0);
+            let last = ((max / step - 0.5).floor() as i64).min(size - 1);
+            first..=last
+        };
+
+        let mut value: Option<f32> = None;
+
+        for z in range(bbox.min.z, bbox.max.z, self.slice_spacing, slices) {
+            for y in range(bbox.min.y, bbox.max.y, self.pixel_size, height) {
+                for x in range(bbox.min.x, bbox.max.x, self.pixel_size, width) {
+                    let v = self.values[((z * height + height - y - 1) * width + x) as usize];
+                    value = Some(value.map_or(v, |max| max.max(v)));
+                }
+            }
+        }
+
+        value
+    }
+}
+
+impl Sampled for Volume {
+    fn bbox(&self) -> Bbox {
+        let size = Vector3::new(
+            self.size[0] as f32 * self.pixel_size,
+            self.size[1] as f32 * self.pixel_size,
+            self.size[2] as f32 * self.slice_spacing,
+        );
+
+        Bbox::new(Vector3::zeros(), size)
+    }
+
+    /// Voxels whose footprint holds an occupied intensity, coloured by the
+    /// window. The highest intensity of the pixels inside a voxel is used,
+    /// or the one under its centre for voxels smaller than the pixels
+    fn voxelize(
+        &self,
+        lattice: &Lattice,
+        transform: &Matrix4<f32>,
+        bar: &ProgressBar,
+    ) -> Vec<Voxel> {
+        let (min_value, max_value) = self
+            .values
+            .iter()
+            .fold((f32::MAX, f32::MIN), |(min, max), v| {
+                (min.min(*v), max.max(*v))
+            });
+
+        let threshold = self
+            .transfer
+            .threshold
+            .unwrap_or(match self.transfer.window {
+                Some((width, level)) => level - width / 2.0,
+                None => (min_value + max_value) / 2.0,
+            });
+
+        let (low, high) = match self.transfer.window {
+            Some((width, level)) => (level - width / 2.0, level + width / 2.0),
+            None => (threshold, max_value),
+        };
+
+        let inverse = transform
+            .try_inverse()
+            .expect("Source transform isn't invertible");
+        let half = lattice.resolution / 2.0;
+
+        let sample = |voxel: &Vector3<i32>, position: &Vector3<f32>| {
+            let center = lattice.center(voxel);
+            let footprint = Bbox::new(center - half, center + half).transform(&inverse);
+
+            let value = self
+                .max(&footprint)
+                .or_else(|| self.sample(position))
+                .filter(|v| *v >= threshold)?;
+
+            let gray = ((value - low) / (high - low).max(f32::EPSILON)).clamp(0.0, 1.0) * 255.0;
+
+            Some([gray as u8, gray as u8, gray as u8, 255])
+        };
+
+        lattice.sample(&self.bbox().transform(transform), transform, sample, bar)
+    }
+}